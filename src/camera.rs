@@ -0,0 +1,241 @@
+use crate::matrix::{Matrix, Vector};
+use crate::points::{Figure, Point, Scalar};
+
+// Vector helpers on plain coordinate arrays.
+
+pub fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+}
+
+pub fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+}
+
+pub fn times(a: [f64; 3], k: f64) -> [f64; 3] {
+    return a.map(|c| c * k);
+}
+
+pub fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    return a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+}
+
+pub fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+}
+
+pub fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let norm = dot(a, a).sqrt();
+    return if norm == 0.0 { a } else { times(a, 1.0 / norm) };
+}
+
+/// Rotation of `v` around the unit vector `axis` (Rodrigues formula).
+fn rotate(v: [f64; 3], axis: [f64; 3], angle: f64) -> [f64; 3] {
+    let (sin, cos) = angle.sin_cos();
+    return add(add(times(v, cos), times(cross(axis, v), sin)), times(axis, dot(axis, v) * (1.0 - cos)));
+}
+
+/// Pinhole camera. Figures use x to the right, y up and z going away from the default viewer,
+/// so the camera right vector is `up × forward`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub eye: Point<3>,
+    pub target: Point<3>,
+    pub up: Point<3>,
+    /// Vertical field of view, in radians.
+    pub fov: f64,
+    pub near: f64,
+    pub far: f64,
+    /// Horizontal image shift per unit of depth, used by off axis stereo eyes.
+    pub shift: f64,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        return Camera {
+            eye: Point { coords: [0.0, 0.0, -500.0] },
+            target: Point { coords: [0.0, 0.0, 0.0] },
+            up: Point { coords: [0.0, 1.0, 0.0] },
+            fov: 60f64.to_radians(),
+            near: 1.0,
+            far: 10000.0,
+            shift: 0.0,
+        };
+    }
+}
+
+impl Camera {
+    /// Default orientation, looking at a sphere of `radius` around `center` that fills the view.
+    pub fn framing(center: Point<3>, radius: f64) -> Camera {
+        let default = Camera::default();
+        let distance = (radius / (default.fov / 2.0).sin() * 1.1).max(default.near * 10.0);
+        return Camera {
+            eye: Point { coords: add(center.coords, [0.0, 0.0, -distance]) },
+            target: center,
+            ..default
+        };
+    }
+
+    /// Frames the bounding sphere of `figure`.
+    pub fn looking_at<T: Scalar>(figure: &Figure<3, T>) -> Camera {
+        let radius = match figure.bounds() {
+            Some((min, max)) => dot(sub(max.coords, min.coords), sub(max.coords, min.coords)).sqrt() / 2.0,
+            None => 0.0
+        };
+        return Camera::framing(figure.centroid(), radius);
+    }
+
+    pub fn distance(&self) -> f64 {
+        return dot(sub(self.target.coords, self.eye.coords), sub(self.target.coords, self.eye.coords)).sqrt();
+    }
+
+    /// Right, up and forward unit vectors.
+    pub fn basis(&self) -> ([f64; 3], [f64; 3], [f64; 3]) {
+        let forward = normalize(sub(self.target.coords, self.eye.coords));
+        let right = normalize(cross(self.up.coords, forward));
+        let up = cross(forward, right);
+        return (right, up, forward);
+    }
+
+    /// World to view coordinates: x right, y up, z depth in front of the eye.
+    pub fn view(&self) -> Matrix<f64, f64, 4> {
+        let (right, up, forward) = self.basis();
+        let line = |axis: [f64; 3]| Vector { values: [axis[0], axis[1], axis[2], -dot(axis, self.eye.coords)] };
+        return Matrix::new([line(right), line(up), line(forward), Vector { values: [0.0, 0.0, 0.0, 1.0] }]);
+    }
+
+    /// View to normalized device coordinates: x and y in [-1, 1] inside the field of view,
+    /// z from -1 at the near plane to 1 at the far plane. `w` holds the depth.
+    pub fn projection(&self, aspect: f64) -> Matrix<f64, f64, 4> {
+        let f = 1.0 / (self.fov / 2.0).tan();
+        let depth = self.far - self.near;
+        return Matrix::new([
+            Vector { values: [f / aspect, 0.0, f / aspect * self.shift, 0.0] },
+            Vector { values: [0.0, f, 0.0, 0.0] },
+            Vector { values: [0.0, 0.0, (self.far + self.near) / depth, -2.0 * self.far * self.near / depth] },
+            Vector { values: [0.0, 0.0, 1.0, 0.0] },
+        ]);
+    }
+
+    pub fn view_projection(&self, aspect: f64) -> Matrix<f64, f64, 4> {
+        return self.projection(aspect) * self.view();
+    }
+
+    pub fn to_view<T: Scalar>(&self, p: &Point<3, T>) -> Point<3> {
+        return p.transform_affine(&self.view());
+    }
+
+    /// Size of a pixel at the target distance, for a viewport `height` pixels high.
+    pub fn pixel_size(&self, height: u32) -> f64 {
+        return self.distance() * (self.fov / 2.0).tan() / (height as f64 / 2.0);
+    }
+
+    /// Turns the eye around the target: `yaw` around the up vector, `pitch` toward it.
+    pub fn orbit(&mut self, yaw: f64, pitch: f64) {
+        let up = normalize(self.up.coords);
+        let mut v = rotate(sub(self.eye.coords, self.target.coords), up, yaw);
+        let (right, _, _) = Camera { eye: Point { coords: add(self.target.coords, v) }, ..*self }.basis();
+        let pitched = rotate(v, right, pitch);
+        // Stop before the poles where the up vector and the view direction are aligned.
+        if dot(normalize(pitched), up).abs() < 0.99 {
+            v = pitched;
+        }
+        self.eye = Point { coords: add(self.target.coords, v) };
+    }
+
+    /// Moves eye and target together, `dx` to the right and `dy` up.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        let (right, up, _) = self.basis();
+        let delta = add(times(right, dx), times(up, dy));
+        self.eye = Point { coords: add(self.eye.coords, delta) };
+        self.target = Point { coords: add(self.target.coords, delta) };
+    }
+
+    /// Multiplies the distance to the target by `factor`.
+    pub fn dolly(&mut self, factor: f64) {
+        let (_, _, forward) = self.basis();
+        let distance = (self.distance() * factor).max(self.near * 2.0);
+        self.eye = Point { coords: sub(self.target.coords, times(forward, distance)) };
+    }
+
+    /// Camera of an eye `offset` to the right, looking parallel to this one. Points at
+    /// `convergence` distance are seen at the same place by both eyes.
+    pub fn stereo_eye(&self, offset: f64, convergence: f64) -> Camera {
+        let (right, _, _) = self.basis();
+        let delta = times(right, offset);
+        return Camera {
+            eye: Point { coords: add(self.eye.coords, delta) },
+            target: Point { coords: add(self.target.coords, delta) },
+            shift: offset / convergence,
+            ..*self
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(expected: [f64; 3], p: Point<3>) {
+        for i in 0..3 {
+            assert!((expected[i] - p.coords[i]).abs() < 1e-6, "{:?} != {:?}", expected, p.coords);
+        }
+    }
+
+    #[test]
+    fn view() {
+        let camera = Camera::default();
+        let p: Point<3> = Point { coords: [10.0, 20.0, 30.0] };
+        assert_near([10.0, 20.0, 530.0], camera.to_view(&p));
+
+        let side = Camera { eye: Point { coords: [500.0, 0.0, 0.0] }, ..camera };
+        assert_near([30.0, 20.0, 490.0], side.to_view(&p));
+    }
+
+    #[test]
+    fn projection() {
+        let camera = Camera::default();
+        let m = camera.view_projection(1.0);
+        // The top of the field of view at the target distance.
+        let top: Point<3> = Point { coords: [0.0, 500.0 * (30f64).to_radians().tan(), 0.0] };
+        let ndc = top.transform_affine(&m);
+        assert!((ndc.coords[1] - 1.0).abs() < 1e-9);
+        let near: Point<3> = Point { coords: [0.0, 0.0, -499.0] };
+        assert!((near.transform_affine(&m).coords[2] + 1.0).abs() < 1e-9);
+        let far: Point<3> = Point { coords: [0.0, 0.0, 9500.0] };
+        assert!((far.transform_affine(&m).coords[2] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn controls() {
+        let mut camera = Camera::default();
+        camera.orbit(std::f64::consts::FRAC_PI_2, 0.0);
+        assert_near([-500.0, 0.0, 0.0], camera.eye);
+        camera.orbit(0.0, std::f64::consts::FRAC_PI_2);
+        // Pitch stops before the pole.
+        assert_near([-500.0, 0.0, 0.0], camera.eye);
+        camera.orbit(0.0, 0.5);
+        assert!(camera.eye.coords[1] > 0.0);
+        assert!((camera.distance() - 500.0).abs() < 1e-9);
+
+        let mut camera = Camera::default();
+        camera.pan(10.0, 20.0);
+        assert_near([10.0, 20.0, -500.0], camera.eye);
+        assert_near([10.0, 20.0, 0.0], camera.target);
+        camera.dolly(0.5);
+        assert_near([10.0, 20.0, -250.0], camera.eye);
+    }
+
+    #[test]
+    fn stereo() {
+        let camera = Camera::default();
+        let left = camera.stereo_eye(-30.0, 500.0);
+        let right = camera.stereo_eye(30.0, 500.0);
+        let m = [left.view_projection(1.0), right.view_projection(1.0)];
+        let on_target: Point<3> = Point { coords: [40.0, 10.0, 0.0] };
+        let l = on_target.transform_affine(&m[0]);
+        let r = on_target.transform_affine(&m[1]);
+        assert!((l.coords[0] - r.coords[0]).abs() < 1e-9);
+        let behind: Point<3> = Point { coords: [40.0, 10.0, 500.0] };
+        assert!(behind.transform_affine(&m[0]).coords[0] < behind.transform_affine(&m[1]).coords[0]);
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::camera::Camera;
+use crate::files::{check_format, extension, read_any_figure, write_any_figure};
+use crate::generators::{generate, Shape, ShapeParameters};
+use crate::hidden::HiddenLines;
+use crate::hyper::HyperView;
+use crate::obj::{obj_losses, Rounding, ScalePolicy};
+use crate::points::{AnyFigure, Error, Figure};
+use crate::projection::{default_screens, Clipping, ProjectionMode, DEFAULT_AREA};
+use crate::raster::{write_png, RasterOptions};
+use crate::shading::{Fill, Shading};
+use crate::stereo::{Anaglyph, StereoLayout, StereoRig};
+use crate::svg::write_svg;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_LOAD_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_INVALID: i32 = 3;
+
+const USAGE: &str = "Usage: Vision3D [<command> <arguments>]
+Without command the viewer window is opened.
+
+Commands:
+  info <file>                      point and edge counts, bounding box
+  validate <file>                  report dangling, self loop and duplicated edges, unused points
+  convert <input> <output>         convert between .fig and .obj
+  generate <shape> <output>        write a generated shape
+         [--size <width>] [--subdivisions <count>] [--dimension <3|4>]
+  render <input> <output>          draw the figure through the viewer screens, .png or .svg
+         [--yaw <degrees>] [--pitch <degrees>]
+         [--xw <degrees>] [--yw <degrees>] [--zw <degrees>]   for 4D figures
+         [--view <mode>[,<right eye mode>]] [--clip <near|frustum>]
+         [--hidden <show|dashed|hide>]
+         [--fill <wireframe|solid|overlay>] [--light <x>,<y>,<z>]
+         [--line-width <pixels>] [--antialias <on|off>]   for .png outputs
+         [--layout <overlay|parallel|cross-eyed>]
+         [--anaglyph <red-cyan|green-magenta|amber-blue>]
+         [--interocular <distance>] [--convergence <depth beyond the center>]
+
+Shapes:
+  hypercube, simplex, cross-polytope, tetrahedron, cube, octahedron, dodecahedron,
+  icosahedron, grid, cylinder, cone, sphere, torus
+
+Projection modes:
+  perspective, front, top, side, isometric, dimetric, cavalier, cabinet
+
+Options for .obj inputs:
+  --scale <factor>                 multiply coordinates (default 1)
+  --round <none|nearest|floor|ceil|truncate>";
+
+/// Positional arguments and `--name value` options of a command line.
+struct Arguments {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Arguments {
+    fn parse(args: &[String]) -> Result<Arguments, Error> {
+        let mut positional: Vec<String> = vec![];
+        let mut options: HashMap<String, String> = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => match iter.next() {
+                    Some(value) => { options.insert(name.to_string(), value.clone()); },
+                    None => return Err(Error { cause: format!("missing value for option --{}", name) })
+                },
+                None => positional.push(arg.clone())
+            }
+        }
+        return Ok(Arguments { positional, options });
+    }
+
+    fn number(&self, name: &str, default: f64) -> Result<f64, Error> {
+        return match self.options.get(name) {
+            Some(v) => v.parse::<f64>()
+                .map_err(|_| Error { cause: format!("--{} expects a number, got '{}'", name, v) }),
+            None => Ok(default)
+        };
+    }
+
+    fn policy(&self) -> Result<ScalePolicy, Error> {
+        let rounding = match self.options.get("round").map(|r| r.as_str()) {
+            None | Some("none") => Rounding::None,
+            Some("nearest") => Rounding::Nearest,
+            Some("floor") => Rounding::Floor,
+            Some("ceil") => Rounding::Ceil,
+            Some("truncate") => Rounding::Truncate,
+            Some(other) => return Err(Error { cause: format!("unknown rounding '{}'", other) })
+        };
+        return Ok(ScalePolicy { scale: self.number("scale", 1.0)?, rounding });
+    }
+
+    fn stereo(&self) -> Result<StereoRig, Error> {
+        let default = StereoRig::default();
+        let anaglyph = match self.options.get("anaglyph") {
+            Some(name) => match Anaglyph::from_name(name) {
+                Some(a) => a,
+                None => return Err(Error { cause: format!("unknown anaglyph '{}'", name) })
+            },
+            None => default.anaglyph
+        };
+        let layout = match self.options.get("layout") {
+            Some(name) => match StereoLayout::from_name(name) {
+                Some(l) => l,
+                None => return Err(Error { cause: format!("unknown layout '{}'", name) })
+            },
+            None => default.layout
+        };
+        return Ok(StereoRig {
+            interocular: self.number("interocular", default.interocular)?,
+            convergence: self.number("convergence", default.convergence)?,
+            anaglyph,
+            layout
+        });
+    }
+
+    fn clipping(&self) -> Result<Clipping, Error> {
+        return match self.options.get("clip").map(|c| c.as_str()) {
+            None | Some("near") => Ok(Clipping::Near),
+            Some("frustum") => Ok(Clipping::Frustum),
+            Some(other) => Err(Error { cause: format!("unknown clipping '{}'", other) })
+        };
+    }
+
+    fn hidden_lines(&self) -> Result<HiddenLines, Error> {
+        return match self.options.get("hidden") {
+            Some(name) => HiddenLines::from_name(name)
+                .ok_or(Error { cause: format!("unknown hidden line mode '{}'", name) }),
+            None => Ok(HiddenLines::Show)
+        };
+    }
+
+    fn shading(&self) -> Result<Shading, Error> {
+        let default = Shading::default();
+        let fill = match self.options.get("fill") {
+            Some(name) => match Fill::from_name(name) {
+                Some(f) => f,
+                None => return Err(Error { cause: format!("unknown fill '{}'", name) })
+            },
+            None => default.fill
+        };
+        let light = match self.options.get("light") {
+            Some(value) => {
+                let numbers: Vec<f64> = value.split(',').map(|n| n.trim().parse::<f64>()).collect::<Result<_, _>>()
+                    .map_err(|_| Error { cause: format!("light direction '{}' is not three numbers", value) })?;
+                match <[f64; 3]>::try_from(numbers) {
+                    Ok(light) => light,
+                    Err(_) => return Err(Error { cause: format!("light direction '{}' is not three numbers", value) })
+                }
+            },
+            None => default.light
+        };
+        return Ok(Shading { fill, light, ..default });
+    }
+
+    fn raster(&self) -> Result<RasterOptions, Error> {
+        let default = RasterOptions::default();
+        let antialias = match self.options.get("antialias").map(|a| a.as_str()) {
+            None => default.antialias,
+            Some("on") => true,
+            Some("off") => false,
+            Some(other) => return Err(Error { cause: format!("--antialias expects on or off, got '{}'", other) })
+        };
+        let line_width = self.number("line-width", default.line_width as f64)?;
+        if line_width <= 0.0 {
+            return Err(Error { cause: format!("line width {} is not positive", line_width) });
+        }
+        return Ok(RasterOptions { line_width: line_width as f32, antialias });
+    }
+
+    /// Projection mode of the left and right screens.
+    fn views(&self) -> Result<[ProjectionMode; 2], Error> {
+        let value = match self.options.get("view") {
+            Some(v) => v,
+            None => return Ok([ProjectionMode::Perspective; 2])
+        };
+        let mut modes: Vec<ProjectionMode> = vec![];
+        for name in value.split(',') {
+            match ProjectionMode::from_name(name) {
+                Some(m) => modes.push(m),
+                None => return Err(Error { cause: format!("unknown projection mode '{}'", name) })
+            }
+        }
+        return match modes.as_slice() {
+            [both] => Ok([*both, *both]),
+            [left, right] => Ok([*left, *right]),
+            _ => Err(Error { cause: format!("--view expects one or two modes, got '{}'", value) })
+        };
+    }
+}
+
+fn usage(message: &str) -> i32 {
+    eprintln!("{}\n\n{}", message, USAGE);
+    return EXIT_USAGE;
+}
+
+fn load_any(path: &str, args: &Arguments) -> Result<AnyFigure, i32> {
+    let policy = args.policy().map_err(|e| usage(&e.cause))?;
+    return read_any_figure(Path::new(path), &policy).map_err(|e| {
+        eprintln!("{}", e.cause);
+        EXIT_LOAD_ERROR
+    });
+}
+
+fn info(path: &str, args: &Arguments) -> Result<i32, i32> {
+    fn describe<const N: usize>(figure: &Figure<N>) {
+        println!("dimension: {}", N);
+        println!("points: {}", figure.points.len());
+        println!("edges: {}", figure.edges.len());
+        match figure.bounds() {
+            Some((min, max)) => println!("bounding box: {:?} to {:?}", min.coords, max.coords),
+            None => println!("bounding box: empty")
+        }
+    }
+    let figure = load_any(path, args)?;
+    println!("file: {}", path);
+    match &figure {
+        AnyFigure::Three(f) => describe(f),
+        AnyFigure::Four(f) => describe(f),
+    }
+    return Ok(EXIT_OK);
+}
+
+fn validate(path: &str, args: &Arguments) -> Result<i32, i32> {
+    let issues = load_any(path, args)?.validate();
+    for issue in issues.iter() {
+        println!("{}", issue);
+    }
+    if issues.is_empty() {
+        println!("{}: valid", path);
+        return Ok(EXIT_OK);
+    }
+    return Ok(EXIT_INVALID);
+}
+
+/// Writes the output figure. A format that can't hold it is a usage error, failing to write it is
+/// reported like failing to read. What an .obj file loses is warned about.
+fn write_output(output: &str, figure: &AnyFigure) -> Result<i32, i32> {
+    let path = Path::new(output);
+    check_format(path, figure.dimension()).map_err(|e| usage(&e.cause))?;
+    if let (AnyFigure::Three(f), "obj") = (figure, extension(path).as_str()) {
+        for loss in obj_losses(f) {
+            eprintln!("warning: {}: {}", output, loss);
+        }
+    }
+    return match write_any_figure(path, figure) {
+        Ok(()) => Ok(EXIT_OK),
+        Err(e) => {
+            eprintln!("{}", e.cause);
+            Err(EXIT_LOAD_ERROR)
+        }
+    };
+}
+
+fn convert(input: &str, output: &str, args: &Arguments) -> Result<i32, i32> {
+    let figure = load_any(input, args)?;
+    return write_output(output, &figure);
+}
+
+fn generate_shape(name: &str, output: &str, args: &Arguments) -> Result<i32, i32> {
+    let shape = match Shape::from_name(name) {
+        Some(s) => s,
+        None => return Err(usage(&format!("unknown shape '{}'", name)))
+    };
+    let default = ShapeParameters::default();
+    let count = |name: &str, default: usize| -> Result<usize, i32> {
+        let value = args.number(name, default as f64).map_err(|e| usage(&e.cause))?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(usage(&format!("--{} expects a whole number, got {}", name, value)));
+        }
+        return Ok(value as usize);
+    };
+    let parameters = ShapeParameters {
+        size: args.number("size", default.size).map_err(|e| usage(&e.cause))?,
+        subdivisions: count("subdivisions", default.subdivisions)?,
+        dimension: count("dimension", default.dimension)?,
+    };
+    let figure = generate(shape, &parameters).map_err(|e| usage(&e.cause))?;
+    return write_output(output, &figure);
+}
+
+fn render(input: &str, output: &str, args: &Arguments) -> Result<i32, i32> {
+    let yaw = args.number("yaw", 0.0).map_err(|e| usage(&e.cause))?;
+    let pitch = args.number("pitch", 0.0).map_err(|e| usage(&e.cause))?;
+    let rig = args.stereo().map_err(|e| usage(&e.cause))?;
+    let views = args.views().map_err(|e| usage(&e.cause))?;
+    let clipping = args.clipping().map_err(|e| usage(&e.cause))?;
+    let hidden_lines = args.hidden_lines().map_err(|e| usage(&e.cause))?;
+    let shading = args.shading().map_err(|e| usage(&e.cause))?;
+    let raster = args.raster().map_err(|e| usage(&e.cause))?;
+    let figure = match load_any(input, args)? {
+        AnyFigure::Three(f) => f,
+        AnyFigure::Four(f) => {
+            let angle = |name: &str| args.number(name, 0.0).map(f64::to_radians).map_err(|e| usage(&e.cause));
+            let view = HyperView { xw: angle("xw")?, yw: angle("yw")?, zw: angle("zw")?, ..HyperView::looking_at(&f) };
+            view.lower_figure(&f)
+        }
+    }.rotated(yaw.to_radians(), pitch.to_radians());
+    let camera = Camera::looking_at(&figure);
+    let mut screens = default_screens(&camera);
+    for (s, mode) in screens.iter_mut().zip(views) {
+        s.mode = mode;
+        s.clipping = clipping;
+        s.hidden_lines = hidden_lines;
+        s.shading = shading;
+    }
+    rig.apply(&mut screens, &camera, DEFAULT_AREA);
+    let path = Path::new(output);
+    let written = match extension(path).as_str() {
+        "png" => write_png(path, &figure, &screens, &raster),
+        "svg" => write_svg(path, &figure, &screens),
+        other => return Err(usage(&format!("unsupported image format '{}'", other)))
+    };
+    return match written {
+        Ok(()) => Ok(EXIT_OK),
+        Err(e) => {
+            eprintln!("{}: {}", output, e);
+            Err(EXIT_LOAD_ERROR)
+        }
+    };
+}
+
+/// Runs a command, `args` excludes the program name. Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some(split) => split,
+        None => return usage("missing command")
+    };
+    let arguments = match Arguments::parse(rest) {
+        Ok(a) => a,
+        Err(e) => return usage(&e.cause)
+    };
+    let files: Vec<&str> = arguments.positional.iter().map(|s| s.as_str()).collect();
+    let result = match (command.as_str(), files.as_slice()) {
+        ("info", [file]) => info(file, &arguments),
+        ("validate", [file]) => validate(file, &arguments),
+        ("convert", [input, output]) => convert(input, output, &arguments),
+        ("generate", [shape, output]) => generate_shape(shape, output, &arguments),
+        ("render", [input, output]) => render(input, output, &arguments),
+        ("help", _) | ("--help", _) => {
+            println!("{}", USAGE);
+            Ok(EXIT_OK)
+        },
+        ("info", _) | ("validate", _) | ("convert", _) | ("generate", _) | ("render", _) =>
+            return usage(&format!("wrong number of arguments for '{}'", command)),
+        _ => return usage(&format!("unknown command '{}'", command))
+    };
+    return match result {
+        Ok(code) => code,
+        Err(code) => code
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::files::read_figure;
+
+    fn args(line: &str) -> Vec<String> {
+        return line.split_whitespace().map(|s| s.to_string()).collect();
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(EXIT_OK, run(&args("info example/cube.fig")));
+        assert_eq!(EXIT_OK, run(&args("validate example/cube.fig")));
+        assert_eq!(EXIT_LOAD_ERROR, run(&args("info example/missing.fig")));
+        assert_eq!(EXIT_USAGE, run(&args("info")));
+        assert_eq!(EXIT_USAGE, run(&args("explode example/cube.fig")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --yaw")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --anaglyph blue-orange")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --view fisheye")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --view top,side,front")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --clip far")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --hidden blurred")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --fill glass")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.svg --light 1,2")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.png --antialias maybe")));
+        assert_eq!(EXIT_USAGE, run(&args("render example/cube.fig out.png --line-width 0")));
+    }
+
+    #[test]
+    fn convert_and_render() {
+        let dir = std::env::temp_dir().join("vision3d_cli_test");
+        fs::create_dir_all(&dir).unwrap();
+        let obj = dir.join("cube.obj");
+        let fig = dir.join("cube.fig");
+        let svg = dir.join("cube.svg");
+        let cmd = |line: String| run(&args(&line));
+
+        assert_eq!(EXIT_OK, cmd(format!("convert example/cube.fig {}", obj.display())));
+        assert_eq!(EXIT_OK, cmd(format!("convert {} {}", obj.display(), fig.display())));
+        let original = read_figure(Path::new("example/cube.fig"), &ScalePolicy::default()).unwrap();
+        let converted = read_figure(&fig, &ScalePolicy::default()).unwrap();
+        assert_eq!(original.points, converted.points);
+        assert_eq!(original.edges, converted.edges);
+        let tesseract = dir.join("tesseract.fig");
+        assert_eq!(EXIT_OK, cmd(format!("convert example/tesseract.fig {}", tesseract.display())));
+        match read_any_figure(&tesseract, &ScalePolicy::default()).unwrap() {
+            AnyFigure::Four(f) => assert_eq!(32, f.edges.len()),
+            _ => panic!("4D figure expected")
+        }
+        assert_eq!(EXIT_USAGE, cmd(format!("convert example/tesseract.fig {}", dir.join("tesseract.obj").display())));
+
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --yaw 30 --pitch 15", svg.display())));
+        assert_eq!(24, fs::read_to_string(&svg).unwrap().matches("<line ").count());
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --layout cross-eyed", svg.display())));
+        assert_eq!(2, fs::read_to_string(&svg).unwrap().matches("width=\"450\"").count());
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --layout parallel --view front,top", svg.display())));
+        // Orthographic views of a cube draw its front and back faces over each other.
+        let lines: Vec<String> = fs::read_to_string(&svg).unwrap().lines()
+            .filter(|l| l.trim_start().starts_with("<line ")).map(|l| l.to_string()).collect();
+        assert!(lines.iter().any(|l| lines.iter().filter(|m| *m == l).count() > 1));
+        assert_eq!(EXIT_USAGE, cmd(format!("convert example/cube.fig {}", dir.join("cube.txt").display())));
+        assert_eq!(EXIT_USAGE, cmd(format!("render example/cube.fig {}", dir.join("cube.txt").display())));
+        assert!(!dir.join("cube.txt").exists());
+        assert_eq!(EXIT_LOAD_ERROR, cmd(format!("convert example/cube.fig {}", dir.join("missing").join("cube.fig").display())));
+
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --yaw 30 --pitch 20 --hidden dashed", svg.display())));
+        assert_eq!(6, fs::read_to_string(&svg).unwrap().matches("stroke-dasharray").count());
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --yaw 30 --fill solid --light 0,1,-1", svg.display())));
+        let solid = fs::read_to_string(&svg).unwrap();
+        assert_eq!((12, 0), (solid.matches("<polygon ").count(), solid.matches("<line ").count()));
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --fill overlay --hidden hide", svg.display())));
+        let overlay = fs::read_to_string(&svg).unwrap();
+        assert_eq!((12, 8), (overlay.matches("<polygon ").count(), overlay.matches("<line ").count()));
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --fill overlay", svg.display())));
+        assert_eq!(overlay, fs::read_to_string(&svg).unwrap());
+        let png = dir.join("cube.png");
+        assert_eq!(EXIT_OK, cmd(format!("render example/cube.fig {} --fill overlay --line-width 3", png.display())));
+        assert!(fs::read(&png).unwrap().starts_with(b"\x89PNG"));
+        assert_eq!(EXIT_OK, cmd(format!("render example/tesseract.fig {} --xw 30 --zw 10", svg.display())));
+        assert_eq!(64, fs::read_to_string(&svg).unwrap().matches("<line ").count());
+        assert_eq!(EXIT_OK, cmd("info example/24cell.fig".to_string()));
+        assert_eq!(EXIT_OK, cmd("validate example/16cell.fig".to_string()));
+
+        let shape = dir.join("shape.fig");
+        assert_eq!(EXIT_OK, cmd(format!("generate hypercube {} --dimension 4 --size 50", shape.display())));
+        assert_eq!(EXIT_OK, cmd(format!("validate {}", shape.display())));
+        assert_eq!(EXIT_OK, cmd(format!("generate torus {} --subdivisions 12", obj.display())));
+        assert_eq!(EXIT_USAGE, cmd(format!("generate hypercube {} --dimension 4", obj.display())));
+        assert_eq!(EXIT_USAGE, cmd(format!("generate blob {}", shape.display())));
+        assert_eq!(EXIT_LOAD_ERROR, cmd(format!("generate torus {}", dir.join("missing").join("torus.fig").display())));
+        assert_eq!(EXIT_USAGE, cmd(format!("generate sphere {} --subdivisions 2.5", shape.display())));
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+
+use crate::obj::{loadObj, saveObj, ScalePolicy};
+use crate::points::{figureDimension, loadFigure, loadFigureN, saveFigure, AnyFigure, Error, Figure};
+
+/// Lower case extension of the path, empty when there is none.
+pub fn extension(path: &Path) -> String {
+    return path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+}
+
+/// Reads a .fig or .obj file, the format is chosen from the extension.
+pub fn read_figure(path: &Path, policy: &ScalePolicy) -> Result<Figure<3>, Error> {
+    let data = fs::read_to_string(path)
+        .map_err(|e| Error { cause: format!("{}: {}", path.display(), e) })?;
+    let figure = match extension(path).as_str() {
+        "obj" => loadObj(data.as_str(), policy),
+        _ => loadFigure(data.as_str())
+    };
+    return figure.map_err(|e| Error { cause: format!("{}: {}", path.display(), e) });
+}
+
+/// Reads a 3D .obj file or a .fig file of 3 or 4 dimensions.
+pub fn read_any_figure(path: &Path, policy: &ScalePolicy) -> Result<AnyFigure, Error> {
+    if extension(path) == "obj" {
+        return read_figure(path, policy).map(AnyFigure::Three);
+    }
+    let data = fs::read_to_string(path)
+        .map_err(|e| Error { cause: format!("{}: {}", path.display(), e) })?;
+    let figure = match figureDimension(data.as_str()) {
+        Ok(3) => loadFigure(data.as_str()).map(AnyFigure::Three),
+        Ok(4) => loadFigureN(data.as_str()).map(AnyFigure::Four),
+        Ok(n) => return Err(Error { cause: format!("{}: {} dimensional figures are not supported", path.display(), n) }),
+        Err(e) => Err(e)
+    };
+    return figure.map_err(|e| Error { cause: format!("{}: {}", path.display(), e) });
+}
+
+/// Checks that the format of the path, chosen from the extension, can hold a figure of `dimension`.
+pub fn check_format(path: &Path, dimension: usize) -> Result<(), Error> {
+    return match (extension(path).as_str(), dimension) {
+        ("fig", _) | ("obj", 3) => Ok(()),
+        ("obj", n) => Err(Error { cause: format!("{}: {} dimensional figures can only be saved as .fig", path.display(), n) }),
+        (other, _) => Err(Error { cause: format!("unsupported figure format '{}'", other) })
+    };
+}
+
+/// Writes a .fig or .obj file, the format is chosen from the extension.
+pub fn write_figure(path: &Path, figure: &Figure<3>) -> Result<(), Error> {
+    check_format(path, 3)?;
+    let data = match extension(path).as_str() {
+        "obj" => saveObj(figure),
+        _ => saveFigure(figure)
+    };
+    return fs::write(path, data)
+        .map_err(|e| Error { cause: format!("{}: {}", path.display(), e) });
+}
+
+/// Writes a figure of any dimension, only .fig files hold more than 3 dimensions.
+pub fn write_any_figure(path: &Path, figure: &AnyFigure) -> Result<(), Error> {
+    return match figure {
+        AnyFigure::Three(f) => write_figure(path, f),
+        AnyFigure::Four(f) => {
+            check_format(path, 4)?;
+            fs::write(path, saveFigure(f)).map_err(|e| Error { cause: format!("{}: {}", path.display(), e) })
+        }
+    };
+}
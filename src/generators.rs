@@ -0,0 +1,530 @@
+use std::f64::consts::PI;
+
+use crate::camera::{cross, dot, normalize, sub};
+use crate::points::{AnyFigure, EdgeAdder, Error, FaceAdder, Figure, PointAdder};
+
+fn distance<const N: usize>(f: &Figure<N>, a: usize, b: usize) -> f64 {
+    return (0..N).map(|i| (f.points[a].coords[i] - f.points[b].coords[i]).powi(2)).sum::<f64>().sqrt();
+}
+
+/// Joins every pair of points at the smallest distance found in the figure.
+fn connect_nearest<const N: usize>(mut f: Figure<N>) -> Figure<N> {
+    let n = f.points.len();
+    let pairs: Vec<(usize, usize)> = (0..n).flat_map(|a| (a + 1..n).map(move |b| (a, b))).collect();
+    let shortest = pairs.iter().map(|&(a, b)| distance(&f, a, b)).fold(f64::INFINITY, f64::min);
+    for (a, b) in pairs {
+        if (distance(&f, a, b) - shortest).abs() < shortest * 1e-9 {
+            f.add_edge(a, b);
+        }
+    }
+    return f;
+}
+
+/// Adds a triangle for every three points joined by edges.
+fn triangles<const N: usize>(mut f: Figure<N>) -> Figure<N> {
+    let joined = |f: &Figure<N>, a: usize, b: usize| f.edges.iter()
+        .any(|e| e.points == (a, b) || e.points == (b, a));
+    let n = f.points.len();
+    for a in 0..n {
+        for b in a + 1..n {
+            for c in b + 1..n {
+                if joined(&f, a, b) && joined(&f, b, c) && joined(&f, a, c) {
+                    f.add_face(&[a, b, c]);
+                }
+            }
+        }
+    }
+    return f;
+}
+
+/// Faces of the convex hull of the points, turning counterclockwise seen from outside.
+fn convex_faces(mut f: Figure<3>) -> Figure<3> {
+    let p: Vec<[f64; 3]> = f.points.iter().map(|p| p.coords).collect();
+    let n = p.len();
+    let scale = p.iter().map(|c| dot(*c, *c).sqrt()).fold(0.0, f64::max);
+    let mut found: Vec<Vec<usize>> = vec![];
+    for a in 0..n {
+        for b in a + 1..n {
+            for c in b + 1..n {
+                let mut normal = normalize(cross(sub(p[b], p[a]), sub(p[c], p[a])));
+                let side: Vec<f64> = p.iter().map(|q| dot(normal, sub(*q, p[a])) / scale).collect();
+                if side.iter().all(|s| *s < 1e-9) {
+                } else if side.iter().all(|s| *s > -1e-9) {
+                    normal = normal.map(|c| -c);
+                } else {
+                    continue;
+                }
+                let mut face: Vec<usize> = (0..n).filter(|i| side[*i].abs() < 1e-9).collect();
+                if found.contains(&face) {
+                    continue;
+                }
+                found.push(face.clone());
+                let center = face.iter().fold([0.0; 3], |s, i| [0, 1, 2].map(|k| s[k] + p[*i][k] / face.len() as f64));
+                let u = normalize(sub(p[face[0]], center));
+                let v = cross(normal, u);
+                let angle = |i: &usize| f64::atan2(dot(v, sub(p[*i], center)), dot(u, sub(p[*i], center)));
+                face.sort_by(|i, j| angle(i).total_cmp(&angle(j)));
+                f.add_face(&face);
+            }
+        }
+    }
+    return f;
+}
+
+/// Moves the points so that the farthest one is `radius` away from the origin.
+fn with_radius<const N: usize>(mut f: Figure<N>, radius: f64) -> Figure<N> {
+    let farthest = f.points.iter()
+        .map(|p| p.coords.iter().map(|c| c * c).sum::<f64>().sqrt())
+        .fold(0.0, f64::max);
+    for p in f.points.iter_mut() {
+        p.coords = p.coords.map(|c| c * radius / farthest);
+    }
+    return f;
+}
+
+/// Points on a circle of the xz plane at height `y`.
+fn circle(f: &mut Figure<3>, radius: f64, y: f64, segments: usize) -> usize {
+    let first = f.points.len();
+    for i in 0..segments {
+        let angle = 2.0 * PI * i as f64 / segments as f64;
+        f.add_point([radius * angle.cos(), y, radius * angle.sin()]);
+    }
+    for i in 0..segments {
+        f.add_edge(first + i, first + (i + 1) % segments);
+    }
+    return first;
+}
+
+/// Cube of N dimensions with edges of length `size`, centered on the origin.
+pub fn hypercube<const N: usize>(size: f64) -> Figure<N> {
+    let mut f = Figure::<N>::new();
+    for corner in 0..(1usize << N) {
+        let mut coords = [0.0; N];
+        for (i, c) in coords.iter_mut().enumerate() {
+            *c = if corner & (1 << i) == 0 { -size / 2.0 } else { size / 2.0 };
+        }
+        f.add_point(coords);
+    }
+    for corner in 0..(1usize << N) {
+        for i in 0..N {
+            if corner & (1 << i) == 0 {
+                f.add_edge(corner, corner | (1 << i));
+            }
+        }
+    }
+    // The squares starting from each corner along two axes.
+    for corner in 0..(1usize << N) {
+        for i in 0..N {
+            for j in i + 1..N {
+                if corner & (1 << i) == 0 && corner & (1 << j) == 0 {
+                    f.add_face(&[corner, corner | (1 << i), corner | (1 << i) | (1 << j), corner | (1 << j)]);
+                }
+            }
+        }
+    }
+    return f;
+}
+
+/// Regular simplex of N + 1 points with edges of length `size`, centered on the origin.
+pub fn simplex<const N: usize>(size: f64) -> Figure<N> {
+    let mut f = Figure::<N>::new();
+    // The unit vectors and the point on the diagonal at the same distance from all of them.
+    let last = (1.0 - ((N + 1) as f64).sqrt()) / N as f64;
+    let center = (1.0 + last) / (N + 1) as f64;
+    for i in 0..=N {
+        let mut coords = [0.0; N];
+        for (j, c) in coords.iter_mut().enumerate() {
+            let v = if i == N { last } else if i == j { 1.0 } else { 0.0 };
+            *c = (v - center) * size / 2f64.sqrt();
+        }
+        f.add_point(coords);
+    }
+    return triangles(connect_nearest(f));
+}
+
+/// Cross-polytope of N dimensions, its points `size / 2` away from the origin on each axis.
+pub fn cross_polytope<const N: usize>(size: f64) -> Figure<N> {
+    let mut f = Figure::<N>::new();
+    for i in 0..N {
+        for sign in [-1.0, 1.0] {
+            let mut coords = [0.0; N];
+            coords[i] = sign * size / 2.0;
+            f.add_point(coords);
+        }
+    }
+    return triangles(connect_nearest(f));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Platonic {
+    Tetrahedron,
+    Cube,
+    Octahedron,
+    Dodecahedron,
+    Icosahedron,
+}
+
+/// Platonic solid inscribed in a sphere of diameter `size`.
+pub fn platonic(solid: Platonic, size: f64) -> Figure<3> {
+    let phi = (1.0 + 5f64.sqrt()) / 2.0;
+    let signs = [-1.0, 1.0];
+    let mut f = Figure::<3>::new();
+    match solid {
+        Platonic::Tetrahedron => {
+            for p in [[1.0, 1.0, 1.0], [1.0, -1.0, -1.0], [-1.0, 1.0, -1.0], [-1.0, -1.0, 1.0]] {
+                f.add_point(p);
+            }
+        },
+        Platonic::Cube => {
+            for p in hypercube::<3>(1.0).points {
+                f.add_point(p.coords);
+            }
+        },
+        Platonic::Octahedron => {
+            for p in cross_polytope::<3>(1.0).points {
+                f.add_point(p.coords);
+            }
+        },
+        Platonic::Dodecahedron => {
+            for x in signs {
+                for y in signs {
+                    for z in signs {
+                        f.add_point([x, y, z]);
+                    }
+                    f.add_point([0.0, x / phi, y * phi]);
+                    f.add_point([x / phi, y * phi, 0.0]);
+                    f.add_point([x * phi, 0.0, y / phi]);
+                }
+            }
+        },
+        Platonic::Icosahedron => {
+            for x in signs {
+                for y in signs {
+                    f.add_point([0.0, x, y * phi]);
+                    f.add_point([x, y * phi, 0.0]);
+                    f.add_point([x * phi, 0.0, y]);
+                }
+            }
+        },
+    }
+    return with_radius(convex_faces(connect_nearest(f)), size / 2.0);
+}
+
+/// Square grid of the xz plane, `size` wide with `cells` cells on each side.
+pub fn grid(size: f64, cells: usize) -> Figure<3> {
+    let mut f = Figure::<3>::new();
+    let step = size / cells as f64;
+    for i in 0..=cells {
+        for j in 0..=cells {
+            f.add_point([i as f64 * step - size / 2.0, 0.0, j as f64 * step - size / 2.0]);
+            let index = i * (cells + 1) + j;
+            if j > 0 {
+                f.add_edge(index - 1, index);
+            }
+            if i > 0 {
+                f.add_edge(index - cells - 1, index);
+            }
+            if i > 0 && j > 0 {
+                f.add_face(&[index - cells - 2, index - 1, index, index - cells - 1]);
+            }
+        }
+    }
+    return f;
+}
+
+/// Vertical cylinder centered on the origin, its circles drawn with `segments` segments.
+pub fn cylinder(radius: f64, height: f64, segments: usize) -> Figure<3> {
+    let mut f = Figure::<3>::new();
+    let bottom = circle(&mut f, radius, -height / 2.0, segments);
+    let top = circle(&mut f, radius, height / 2.0, segments);
+    for i in 0..segments {
+        let next = (i + 1) % segments;
+        f.add_edge(bottom + i, top + i);
+        f.add_face(&[bottom + i, top + i, top + next, bottom + next]);
+    }
+    f.add_face(&(0..segments).map(|i| bottom + i).collect::<Vec<usize>>());
+    f.add_face(&(0..segments).rev().map(|i| top + i).collect::<Vec<usize>>());
+    return f;
+}
+
+/// Vertical cone centered on the origin, pointing up.
+pub fn cone(radius: f64, height: f64, segments: usize) -> Figure<3> {
+    let mut f = Figure::<3>::new();
+    let base = circle(&mut f, radius, -height / 2.0, segments);
+    f.add_point([0.0, height / 2.0, 0.0]);
+    let apex = f.points.len() - 1;
+    for i in 0..segments {
+        f.add_edge(base + i, apex);
+        f.add_face(&[base + i, apex, base + (i + 1) % segments]);
+    }
+    f.add_face(&(0..segments).map(|i| base + i).collect::<Vec<usize>>());
+    return f;
+}
+
+/// Sphere drawn with `segments` meridians and `segments / 2` slices between the poles.
+pub fn sphere(radius: f64, segments: usize) -> Figure<3> {
+    let mut f = Figure::<3>::new();
+    let slices = (segments / 2).max(2);
+    let mut rings: Vec<usize> = vec![];
+    for k in 1..slices {
+        let latitude = PI * k as f64 / slices as f64;
+        rings.push(circle(&mut f, radius * latitude.sin(), radius * latitude.cos(), segments));
+    }
+    f.add_point([0.0, radius, 0.0]);
+    f.add_point([0.0, -radius, 0.0]);
+    let (north, south) = (f.points.len() - 2, f.points.len() - 1);
+    for i in 0..segments {
+        let next = (i + 1) % segments;
+        f.add_edge(north, rings[0] + i);
+        f.add_face(&[north, rings[0] + next, rings[0] + i]);
+        for pair in rings.windows(2) {
+            f.add_edge(pair[0] + i, pair[1] + i);
+            f.add_face(&[pair[0] + i, pair[0] + next, pair[1] + next, pair[1] + i]);
+        }
+        let last = rings[rings.len() - 1];
+        f.add_edge(last + i, south);
+        f.add_face(&[last + i, last + next, south]);
+    }
+    return f;
+}
+
+/// Torus around the y axis, tube circles of `minor` radius `major` away from the axis.
+pub fn torus(major: f64, minor: f64, segments: usize) -> Figure<3> {
+    let mut f = Figure::<3>::new();
+    let tube = (segments / 2).max(3);
+    for i in 0..segments {
+        let around = 2.0 * PI * i as f64 / segments as f64;
+        for j in 0..tube {
+            let inside = 2.0 * PI * j as f64 / tube as f64;
+            let r = major + minor * inside.cos();
+            f.add_point([r * around.cos(), minor * inside.sin(), r * around.sin()]);
+            let index = i * tube + j;
+            let (up, around) = (i * tube + (j + 1) % tube, ((i + 1) % segments) * tube + j);
+            f.add_edge(index, up);
+            f.add_edge(index, around);
+            f.add_face(&[index, up, ((i + 1) % segments) * tube + (j + 1) % tube, around]);
+        }
+    }
+    return f;
+}
+
+/// Shapes offered by the "New" menu and the generate command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Hypercube,
+    Simplex,
+    CrossPolytope,
+    Platonic(Platonic),
+    Grid,
+    Cylinder,
+    Cone,
+    Sphere,
+    Torus,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 13] = [
+        Shape::Hypercube, Shape::Simplex, Shape::CrossPolytope,
+        Shape::Platonic(Platonic::Tetrahedron), Shape::Platonic(Platonic::Cube),
+        Shape::Platonic(Platonic::Octahedron), Shape::Platonic(Platonic::Dodecahedron),
+        Shape::Platonic(Platonic::Icosahedron),
+        Shape::Grid, Shape::Cylinder, Shape::Cone, Shape::Sphere, Shape::Torus,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Hypercube => "hypercube",
+            Shape::Simplex => "simplex",
+            Shape::CrossPolytope => "cross-polytope",
+            Shape::Platonic(Platonic::Tetrahedron) => "tetrahedron",
+            Shape::Platonic(Platonic::Cube) => "cube",
+            Shape::Platonic(Platonic::Octahedron) => "octahedron",
+            Shape::Platonic(Platonic::Dodecahedron) => "dodecahedron",
+            Shape::Platonic(Platonic::Icosahedron) => "icosahedron",
+            Shape::Grid => "grid",
+            Shape::Cylinder => "cylinder",
+            Shape::Cone => "cone",
+            Shape::Sphere => "sphere",
+            Shape::Torus => "torus",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Shape> {
+        return Shape::ALL.iter().find(|s| s.name() == name).copied();
+    }
+
+    /// True for the shapes that exist in any dimension.
+    pub fn has_dimension(&self) -> bool {
+        return matches!(self, Shape::Hypercube | Shape::Simplex | Shape::CrossPolytope);
+    }
+
+    /// Least number of subdivisions of the shapes made of cells or segments, None for the others.
+    pub fn min_subdivisions(&self) -> Option<usize> {
+        return match self {
+            Shape::Grid => Some(1),
+            Shape::Cylinder | Shape::Cone | Shape::Sphere | Shape::Torus => Some(3),
+            _ => None
+        };
+    }
+}
+
+/// Size and level of detail of generated shapes.
+#[derive(Debug, Clone, Copy)]
+pub struct ShapeParameters {
+    /// Overall width of the shape.
+    pub size: f64,
+    /// Cells of grids, segments of the round shapes.
+    pub subdivisions: usize,
+    /// Dimension of hypercubes, simplices and cross-polytopes, 3 or 4.
+    pub dimension: usize,
+}
+
+impl Default for ShapeParameters {
+    fn default() -> Self {
+        return ShapeParameters { size: 200.0, subdivisions: 16, dimension: 3 };
+    }
+}
+
+/// Shape existing in any dimension, built in the one of the parameters.
+fn in_dimension(shape: Shape, parameters: &ShapeParameters, three: fn(f64) -> Figure<3>, four: fn(f64) -> Figure<4>)
+    -> Result<AnyFigure, Error> {
+    return match parameters.dimension {
+        3 => Ok(AnyFigure::Three(three(parameters.size))),
+        4 => Ok(AnyFigure::Four(four(parameters.size))),
+        d => Err(Error { cause: format!("{} dimensional {} is not supported", d, shape.name()) })
+    };
+}
+
+pub fn generate(shape: Shape, parameters: &ShapeParameters) -> Result<AnyFigure, Error> {
+    let size = parameters.size;
+    if !(size > 0.0 && size.is_finite()) {
+        return Err(Error { cause: format!("shape size should be positive, got {}", size) });
+    }
+    let n = parameters.subdivisions;
+    if let Some(minimum) = shape.min_subdivisions().filter(|m| n < *m) {
+        return Err(Error { cause: format!("{} needs at least {} subdivisions", shape.name(), minimum) });
+    }
+    return match shape {
+        Shape::Hypercube => in_dimension(shape, parameters, hypercube::<3>, hypercube::<4>),
+        Shape::Simplex => in_dimension(shape, parameters, simplex::<3>, simplex::<4>),
+        Shape::CrossPolytope => in_dimension(shape, parameters, cross_polytope::<3>, cross_polytope::<4>),
+        Shape::Platonic(solid) => Ok(AnyFigure::Three(platonic(solid, size))),
+        Shape::Grid => Ok(AnyFigure::Three(grid(size, n))),
+        Shape::Cylinder => Ok(AnyFigure::Three(cylinder(size / 2.0, size, n))),
+        Shape::Cone => Ok(AnyFigure::Three(cone(size / 2.0, size, n))),
+        Shape::Sphere => Ok(AnyFigure::Three(sphere(size / 2.0, n))),
+        Shape::Torus => Ok(AnyFigure::Three(torus(size * 0.35, size * 0.15, n))),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts<const N: usize>(f: &Figure<N>) -> (usize, usize) {
+        assert!(f.validate().is_empty(), "{:?}", f.validate());
+        return (f.points.len(), f.edges.len());
+    }
+
+    /// Every side of every face has to be an edge.
+    fn faces<const N: usize>(f: &Figure<N>) -> usize {
+        for face in f.faces.iter() {
+            for (i, a) in face.points.iter().enumerate() {
+                let b = face.points[(i + 1) % face.points.len()];
+                assert!(f.edges.iter().any(|e| e.points == (*a, b) || e.points == (b, *a)), "{:?}", face);
+            }
+        }
+        return f.faces.len();
+    }
+
+    fn edge_lengths<const N: usize>(f: &Figure<N>) -> Vec<f64> {
+        return f.edges.iter().map(|e| distance(f, e.points.0, e.points.1)).collect();
+    }
+
+    #[test]
+    fn polytopes() {
+        assert_eq!((8, 12), counts(&hypercube::<3>(2.0)));
+        assert_eq!((16, 32), counts(&hypercube::<4>(2.0)));
+        assert_eq!((32, 80), counts(&hypercube::<5>(2.0)));
+        assert!(edge_lengths(&hypercube::<4>(2.0)).iter().all(|l| (l - 2.0).abs() < 1e-9));
+
+        let s = simplex::<4>(10.0);
+        assert_eq!((5, 10), counts(&s));
+        assert!(edge_lengths(&s).iter().all(|l| (l - 10.0).abs() < 1e-9));
+        assert!(s.centroid().coords.iter().all(|c| c.abs() < 1e-9));
+        assert_eq!((4, 6), counts(&simplex::<3>(1.0)));
+
+        assert_eq!((6, 12), counts(&cross_polytope::<3>(2.0)));
+        assert_eq!((8, 24), counts(&cross_polytope::<4>(2.0)));
+
+        assert_eq!(6, faces(&hypercube::<3>(1.0)));
+        assert_eq!(24, faces(&hypercube::<4>(1.0)));
+        assert_eq!(4, faces(&simplex::<3>(1.0)));
+        assert_eq!(10, faces(&simplex::<4>(1.0)));
+        assert_eq!(32, faces(&cross_polytope::<4>(1.0)));
+    }
+
+    #[test]
+    fn platonic_solids() {
+        let expected = [
+            (Platonic::Tetrahedron, 4, 6, 4),
+            (Platonic::Cube, 8, 12, 6),
+            (Platonic::Octahedron, 6, 12, 8),
+            (Platonic::Dodecahedron, 20, 30, 12),
+            (Platonic::Icosahedron, 12, 30, 20),
+        ];
+        for (solid, points, edges, sides) in expected {
+            let f = platonic(solid, 200.0);
+            assert_eq!((points, edges), counts(&f), "{:?}", solid);
+            assert_eq!(sides, faces(&f), "{:?}", solid);
+            // Counterclockwise seen from outside.
+            for face in f.faces.iter() {
+                let corners: Vec<[f64; 3]> = f.face_points(face).iter().map(|p| p.coords).collect();
+                assert!(dot(crate::hidden::face_normal(&corners), corners[0]) > 0.0);
+            }
+            for p in f.points.iter() {
+                let r = p.coords.iter().map(|c| c * c).sum::<f64>().sqrt();
+                assert!((r - 100.0).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn round_shapes() {
+        assert_eq!((9, 12), counts(&grid(10.0, 2)));
+        assert_eq!((16, 24), counts(&cylinder(1.0, 2.0, 8)));
+        assert_eq!((9, 16), counts(&cone(1.0, 2.0, 8)));
+        // 3 rings of 8 points plus the poles, edges along the rings and the meridians.
+        assert_eq!((26, 56), counts(&sphere(1.0, 8)));
+        assert_eq!((32, 64), counts(&torus(3.0, 1.0, 8)));
+        assert_eq!(4, faces(&grid(10.0, 2)));
+        assert_eq!(10, faces(&cylinder(1.0, 2.0, 8)));
+        assert_eq!(9, faces(&cone(1.0, 2.0, 8)));
+        assert_eq!(32, faces(&sphere(1.0, 8)));
+        assert_eq!(32, faces(&torus(3.0, 1.0, 8)));
+    }
+
+    #[test]
+    fn shapes() {
+        for shape in Shape::ALL {
+            assert_eq!(Some(shape), Shape::from_name(shape.name()));
+            let figure = generate(shape, &ShapeParameters::default()).unwrap();
+            assert!(figure.validate().is_empty(), "{}", shape.name());
+        }
+        let four = ShapeParameters { dimension: 4, ..ShapeParameters::default() };
+        assert!(matches!(generate(Shape::Hypercube, &four), Ok(AnyFigure::Four(_))));
+        assert!(matches!(generate(Shape::Torus, &four), Ok(AnyFigure::Three(_))));
+        let five = ShapeParameters { dimension: 5, ..ShapeParameters::default() };
+        assert!(generate(Shape::Simplex, &five).is_err());
+        let coarse = ShapeParameters { subdivisions: 2, ..ShapeParameters::default() };
+        assert!(generate(Shape::Sphere, &coarse).is_err());
+        assert!(generate(Shape::Grid, &coarse).is_ok());
+        // Subdivisions don't matter to polytopes.
+        let none = ShapeParameters { subdivisions: 0, ..ShapeParameters::default() };
+        assert!(generate(Shape::Hypercube, &none).is_ok());
+        assert!(generate(Shape::Platonic(Platonic::Icosahedron), &none).is_ok());
+        assert!(generate(Shape::Grid, &none).is_err());
+        let flat = ShapeParameters { size: 0.0, ..ShapeParameters::default() };
+        assert!(generate(Shape::Hypercube, &flat).is_err());
+    }
+}
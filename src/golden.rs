@@ -0,0 +1,157 @@
+//! Golden image tests: every figure of `example/` is drawn by the rasterizer through fixed
+//! screens and compared with the PNG files of `golden/`. Setting `UPDATE_GOLDEN=1` rewrites them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eframe::epaint::Color32;
+
+use crate::camera::Camera;
+use crate::files::read_any_figure;
+use crate::hidden::HiddenLines;
+use crate::hyper::HyperView;
+use crate::obj::ScalePolicy;
+use crate::points::{AnyFigure, Error, Figure};
+use crate::projection::{default_screens, ProjectionMode, Screen};
+use crate::raster::{render_raster, Raster, RasterOptions};
+use crate::shading::Fill;
+use crate::stereo::StereoRig;
+
+const GOLDEN_DIR: &str = "golden";
+const UPDATE: &str = "UPDATE_GOLDEN";
+/// Drawing area of the golden images, small to keep them light.
+const AREA: [u32; 2] = [240, 180];
+/// Largest channel difference of pixels still taken as equal.
+const CHANNEL_TOLERANCE: u8 = 24;
+/// Share of the pixels allowed to differ more than that.
+const PIXEL_TOLERANCE: f64 = 0.002;
+
+/// Names of the fixed screen setups, part of the golden file names.
+/// "solid" is only drawn for figures with faces, others would look as in "perspective".
+const SETUPS: [&str; 4] = ["perspective", "isometric-dashed", "solid", "anaglyph"];
+
+fn screens(setup: &str, camera: &Camera) -> Vec<Screen> {
+    let mut screens = default_screens(camera);
+    StereoRig::default().apply(&mut screens, camera, AREA);
+    if setup == "anaglyph" {
+        return screens.into();
+    }
+    let [mut s, _] = screens;
+    s.camera = *camera;
+    s.color = Color32::WHITE;
+    match setup {
+        "isometric-dashed" => {
+            s.mode = ProjectionMode::Isometric;
+            s.hidden_lines = HiddenLines::Dashed;
+        },
+        "solid" => {
+            s.shading.fill = Fill::Overlay;
+            s.hidden_lines = HiddenLines::Hide;
+        },
+        _ => {}
+    }
+    return vec![s];
+}
+
+/// The example turned so that no edge is seen end on, 4D figures lowered with a fixed view.
+fn example(path: &Path) -> Result<Figure<3>, Error> {
+    let figure = match read_any_figure(path, &ScalePolicy::default())? {
+        AnyFigure::Three(f) => f,
+        AnyFigure::Four(f) => HyperView { xw: 0.4, yw: 0.3, ..HyperView::looking_at(&f) }.lower_figure(&f),
+    };
+    return Ok(figure.rotated(30f64.to_radians(), 20f64.to_radians()));
+}
+
+fn read_png(path: &Path) -> Result<Raster, String> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut reader = png::Decoder::new(file).read_info().map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(format!("{}: not an 8 bit RGBA image", path.display()));
+    }
+    let mut raster = Raster::new(info.width as usize, info.height as usize, Color32::TRANSPARENT);
+    for (pixel, rgba) in raster.pixels.iter_mut().zip(data.chunks(4)) {
+        *pixel = Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]);
+    }
+    return Ok(raster);
+}
+
+/// Checks that the images are alike, within the tolerances.
+fn compare(expected: &Raster, actual: &Raster) -> Result<(), String> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(format!("size {}x{} instead of {}x{}", actual.width, actual.height, expected.width, expected.height));
+    }
+    let differing = expected.pixels.iter().zip(actual.pixels.iter())
+        .filter(|(e, a)| {
+            let (e, a) = (e.to_array(), a.to_array());
+            (0..4).any(|i| e[i].abs_diff(a[i]) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let allowed = (PIXEL_TOLERANCE * expected.pixels.len() as f64) as usize;
+    if differing > allowed {
+        return Err(format!("{} pixels differ, at most {} may", differing, allowed));
+    }
+    return Ok(());
+}
+
+/// Compares a rendering with its golden image, or writes the golden image when updating.
+/// Differing renderings are saved in the temporary directory to be looked at.
+fn check(name: &str, actual: &Raster) -> Result<(), String> {
+    let golden: PathBuf = [GOLDEN_DIR, &format!("{}.png", name)].iter().collect();
+    let png = actual.encode_png().map_err(|e| e.to_string())?;
+    if std::env::var(UPDATE).is_ok_and(|v| v == "1") {
+        fs::create_dir_all(GOLDEN_DIR).map_err(|e| e.to_string())?;
+        return fs::write(&golden, png).map_err(|e| format!("{}: {}", golden.display(), e));
+    }
+    if !golden.exists() {
+        return Err(format!("{} is missing, run the tests with {}=1 to create it", golden.display(), UPDATE));
+    }
+    return compare(&read_png(&golden)?, actual).map_err(|e| {
+        let saved = std::env::temp_dir().join(format!("{}.png", name));
+        let _ = fs::write(&saved, &png);
+        format!("{}: {}, rendering saved to {}", golden.display(), e, saved.display())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn examples() {
+        let mut paths: Vec<PathBuf> = fs::read_dir("example").unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| p.is_file())
+            .collect();
+        paths.sort();
+        let mut failures: Vec<String> = vec![];
+        for path in paths {
+            let figure = example(&path).unwrap();
+            let camera = Camera::looking_at(&figure);
+            let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+            for setup in SETUPS {
+                if setup == "solid" && figure.faces.is_empty() {
+                    continue;
+                }
+                let raster = render_raster(&figure, &screens(setup, &camera), &RasterOptions::default());
+                if let Err(e) = check(&format!("{}-{}", stem, setup), &raster) {
+                    failures.push(e);
+                }
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn tolerance() {
+        let expected = Raster::new(100, 100, Color32::BLACK);
+        let mut actual = Raster::new(100, 100, Color32::BLACK);
+        actual.pixels[0] = Color32::WHITE;
+        actual.pixels[1] = Color32::from_gray(CHANNEL_TOLERANCE);
+        assert_eq!(Ok(()), compare(&expected, &actual));
+        actual.pixels[..21].fill(Color32::WHITE);
+        assert_eq!(Err("21 pixels differ, at most 20 may".to_string()), compare(&expected, &actual));
+        assert!(compare(&expected, &Raster::new(100, 50, Color32::BLACK)).is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::camera::{add, cross, dot, normalize, sub, times, Camera};
+use crate::points::{Dash, Edge, Face, Figure, Point};
+use crate::projection::{Projection, ProjectionMode, Screen};
+
+/// How edges behind faces are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HiddenLines {
+    /// Every edge drawn solid, faces are ignored.
+    Show,
+    Dashed,
+    Hide,
+}
+
+impl HiddenLines {
+    pub const ALL: [HiddenLines; 3] = [HiddenLines::Show, HiddenLines::Dashed, HiddenLines::Hide];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HiddenLines::Show => "show",
+            HiddenLines::Dashed => "dashed",
+            HiddenLines::Hide => "hide",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<HiddenLines> {
+        return HiddenLines::ALL.iter().find(|h| h.name() == name).copied();
+    }
+}
+
+/// Unit normal of a polygon (Newell's method), oriented by the order of its points.
+pub fn face_normal(points: &[[f64; 3]]) -> [f64; 3] {
+    let mut n = [0.0; 3];
+    for (i, p) in points.iter().enumerate() {
+        let q = points[(i + 1) % points.len()];
+        n = add(n, [(p[1] - q[1]) * (p[2] + q[2]), (p[2] - q[2]) * (p[0] + q[0]), (p[0] - q[0]) * (p[1] + q[1])]);
+    }
+    return normalize(n);
+}
+
+/// Even-odd test of a point lying in the plane of the polygon.
+fn inside(polygon: &[[f64; 3]], normal: [f64; 3], p: [f64; 3]) -> bool {
+    // Drop the coordinate along which the polygon is the most flat.
+    let drop = (0..3).max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs())).unwrap();
+    let (u, v) = ((drop + 1) % 3, (drop + 2) % 3);
+    let mut result = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a[v] > p[v]) != (b[v] > p[v]) && p[u] < a[u] + (p[v] - a[v]) * (b[u] - a[u]) / (b[v] - a[v]) {
+            result = !result;
+        }
+    }
+    return result;
+}
+
+/// Direction from `p` toward the viewer of the screen, and how far the viewer is along it.
+pub fn toward_viewer(screen: &Screen, p: [f64; 3]) -> ([f64; 3], f64) {
+    return match screen.mode.axes() {
+        None => (sub(screen.camera.eye.coords, p), 1.0),
+        // Parallel projections look along the direction both screen axes are perpendicular to.
+        Some([x, y]) => (normalize(cross(y, x)), f64::INFINITY),
+    };
+}
+
+pub fn corners(figure: &Figure<3>, face: &Face) -> Vec<[f64; 3]> {
+    return figure.face_points(face).iter().map(|p| p.coords).collect();
+}
+
+/// Part of an edge between two fractions of its length.
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    pub from: f64,
+    pub to: f64,
+    pub visible: bool,
+}
+
+/// Face as seen through a screen, to find the parts of edges behind it.
+struct Occluder<'a> {
+    face: &'a Face,
+    polygon: Vec<[f64; 3]>,
+    normal: [f64; 3],
+    /// Projected box of the face, None when some corner is behind the near plane.
+    bounds: Option<[[f64; 2]; 2]>,
+}
+
+impl<'a> Occluder<'a> {
+    /// True when the face is between `p` and the viewer.
+    fn hides(&self, screen: &Screen, p: [f64; 3]) -> bool {
+        let (direction, end) = toward_viewer(screen, p);
+        let facing = dot(self.normal, direction);
+        if facing.abs() < 1e-12 {
+            return false;
+        }
+        let t = dot(self.normal, sub(self.polygon[0], p)) / facing;
+        return t > 1e-9 && t < end && inside(&self.polygon, self.normal, add(p, times(direction, t)));
+    }
+
+    /// Fractions of the segment from `a` to `b` where it can go behind the face or come out:
+    /// through the plane of the face, or through a plane joining one of its sides to the viewer.
+    fn crossings(&self, screen: &Screen, a: [f64; 3], b: [f64; 3], into: &mut Vec<f64>) {
+        let mut through = |normal: [f64; 3], on: [f64; 3]| {
+            let t = dot(normal, sub(on, a)) / dot(normal, sub(b, a));
+            if t > 0.0 && t < 1.0 {
+                into.push(t);
+            }
+        };
+        through(self.normal, self.polygon[0]);
+        for (i, q) in self.polygon.iter().enumerate() {
+            let next = self.polygon[(i + 1) % self.polygon.len()];
+            through(cross(sub(next, *q), toward_viewer(screen, *q).0), *q);
+        }
+    }
+}
+
+/// Projected box of points, None when one is behind the near plane.
+fn bounds(screen: &Screen, points: &[[f64; 3]]) -> Option<[[f64; 2]; 2]> {
+    let mut result = [[f64::INFINITY; 2], [f64::NEG_INFINITY; 2]];
+    for p in points {
+        let q = screen.visible_point(&Point { coords: *p })?;
+        for i in 0..2 {
+            result[0][i] = result[0][i].min(q.coords[i]);
+            result[1][i] = result[1][i].max(q.coords[i]);
+        }
+    }
+    return Some(result);
+}
+
+fn overlap(a: Option<[[f64; 2]; 2]>, b: Option<[[f64; 2]; 2]>) -> bool {
+    return match (a, b) {
+        (Some(a), Some(b)) => (0..2).all(|i| a[0][i] <= b[1][i] + 1e-6 && b[0][i] <= a[1][i] + 1e-6),
+        _ => true,
+    };
+}
+
+/// Splits an edge in visible and hidden runs at the exact points where it goes behind faces.
+fn edge_runs(screen: &Screen, figure: &Figure<3>, occluders: &[Occluder], edge: &Edge) -> Vec<Run> {
+    let (a, b) = figure.edge_points(edge);
+    let (a, b) = (a.coords, b.coords);
+    let area = bounds(screen, &[a, b]);
+    // Faces holding the edge don't hide it, faces elsewhere on the screen can't.
+    let candidates: Vec<&Occluder> = occluders.iter()
+        .filter(|o| !(o.face.points.contains(&edge.points.0) && o.face.points.contains(&edge.points.1)))
+        .filter(|o| overlap(o.bounds, area))
+        .collect();
+    let mut splits = vec![0.0, 1.0];
+    for o in candidates.iter() {
+        o.crossings(screen, a, b, &mut splits);
+    }
+    splits.sort_by(f64::total_cmp);
+    let mut runs: Vec<Run> = vec![];
+    for pair in splits.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if to - from < 1e-12 {
+            continue;
+        }
+        let middle = add(a, times(sub(b, a), (from + to) / 2.0));
+        let visible = !candidates.iter().any(|o| o.hides(screen, middle));
+        match runs.last_mut() {
+            Some(run) if run.visible == visible => run.to = to,
+            _ => runs.push(Run { from, to, visible }),
+        }
+    }
+    return runs;
+}
+
+/// Visible and hidden runs of every edge of the figure, as seen through the screen.
+pub fn figure_runs(screen: &Screen, figure: &Figure<3>) -> Vec<Vec<Run>> {
+    if screen.edge_hiding() == HiddenLines::Show || figure.faces.is_empty() {
+        return figure.edges.iter().map(|_| vec![Run { from: 0.0, to: 1.0, visible: true }]).collect();
+    }
+    let occluders: Vec<Occluder> = figure.faces.iter()
+        .map(|face| {
+            let polygon = corners(figure, face);
+            Occluder { face, normal: face_normal(&polygon), bounds: bounds(screen, &polygon), polygon }
+        })
+        .collect();
+    return figure.edges.iter().map(|e| edge_runs(screen, figure, &occluders, e)).collect();
+}
+
+/// What the runs of a figure depend on.
+type RunKey = (Camera, ProjectionMode, HiddenLines, Vec<Point<3>>, Vec<Edge>, Vec<Face>);
+
+/// Runs of the edges of a figure through a screen, kept until the viewpoint or the figure changes.
+#[derive(Default)]
+pub struct RunCache {
+    key: Option<RunKey>,
+    runs: Vec<Vec<Run>>,
+}
+
+impl RunCache {
+    pub fn runs(&mut self, screen: &Screen, figure: &Figure<3>) -> &[Vec<Run>] {
+        let fresh = self.key.as_ref().is_some_and(|(camera, mode, hidden, points, edges, faces)| {
+            *camera == screen.camera && *mode == screen.mode && *hidden == screen.edge_hiding()
+                && *points == figure.points && *edges == figure.edges && *faces == figure.faces
+        });
+        if !fresh {
+            self.runs = figure_runs(screen, figure);
+            self.key = Some((screen.camera, screen.mode, screen.edge_hiding(),
+                             figure.points.clone(), figure.edges.clone(), figure.faces.clone()));
+        }
+        return &self.runs;
+    }
+}
+
+/// Projected part of an edge, `hidden` when it is behind a face and drawn dashed.
+pub struct Piece {
+    pub ends: [Point<2>; 2],
+    /// Screen depths of the ends, taken before clipping.
+    pub depths: [f64; 2],
+    pub hidden: bool,
+}
+
+impl Piece {
+    /// Pattern of the piece of an edge of this style, hidden pieces are always dashed.
+    pub fn dash(&self, edge: &Edge) -> Dash {
+        return if self.hidden { Dash::Dashed } else { edge.style.dash };
+    }
+}
+
+/// What the screen draws of an edge with its `edge_hiding` mode, given the `runs` of the edge.
+pub fn edge_pieces(screen: &Screen, figure: &Figure<3>, edge: &Edge, runs: &[Run]) -> Vec<Piece> {
+    let (a, b) = figure.edge_points(edge);
+    let at = |t: f64| -> Point<3> { Point { coords: add(a.coords, times(sub(b.coords, a.coords), t)) } };
+    let piece = |from: f64, to: f64, hidden: bool| -> Option<Piece> {
+        let (a, b) = (at(from), at(to));
+        return screen.project_segment(&a, &b)
+            .map(|ends| Piece { ends, depths: [screen.depth(&a), screen.depth(&b)], hidden });
+    };
+    return runs.iter()
+        .filter(|run| run.visible || screen.edge_hiding() == HiddenLines::Dashed)
+        .filter_map(|run| piece(run.from, run.to, !run.visible))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::points::{loadFigure, EdgeStyle};
+    use crate::projection::default_screens;
+    use crate::shading::Fill;
+
+    fn cube() -> Figure<3> {
+        return loadFigure(&std::fs::read_to_string("example/cube.fig").unwrap()).unwrap();
+    }
+
+    fn hidden_edges(screen: &Screen, figure: &Figure<3>) -> usize {
+        return figure_runs(screen, figure).iter()
+            .filter(|runs| runs.iter().any(|r| !r.visible))
+            .count();
+    }
+
+    #[test]
+    fn cube_from_front() {
+        let figure = cube();
+        assert_eq!(6, figure.faces.len());
+        let mut camera = Camera::looking_at(&figure);
+        let mut screens = default_screens(&camera);
+        screens[0].hidden_lines = HiddenLines::Hide;
+        // Seen straight from the front, only the front face is visible.
+        assert_eq!(8, hidden_edges(&screens[0], &figure));
+        // Seen from a corner, the three edges of the opposite corner are hidden.
+        screens[0].mode = ProjectionMode::Isometric;
+        assert_eq!(3, hidden_edges(&screens[0], &figure));
+        camera.orbit(0.5, 0.4);
+        screens = default_screens(&camera);
+        screens[0].hidden_lines = HiddenLines::Hide;
+        assert_eq!(3, hidden_edges(&screens[0], &figure));
+        screens[0].hidden_lines = HiddenLines::Show;
+        assert_eq!(0, hidden_edges(&screens[0], &figure));
+        // Over shaded faces the back edges are hidden anyway.
+        screens[0].shading.fill = Fill::Overlay;
+        assert_eq!(3, hidden_edges(&screens[0], &figure));
+    }
+
+    #[test]
+    fn exact_crossings() {
+        // A square seen from the front, and an edge behind it twice as wide.
+        let p = |x: f64, y: f64, z: f64| Point { coords: [x, y, z] };
+        let mut figure = Figure {
+            points: vec![p(-10.0, -10.0, 0.0), p(10.0, -10.0, 0.0), p(10.0, 10.0, 0.0), p(-10.0, 10.0, 0.0),
+                         p(-20.0, 0.0, 10.0), p(20.0, 0.0, 10.0)],
+            edges: vec![Edge { points: (4, 5), style: EdgeStyle::default() }],
+            faces: vec![Face { points: vec![0, 1, 2, 3] }],
+            ..Figure::new()
+        };
+        let [mut screen, _] = default_screens(&Camera::looking_at(&figure));
+        screen.mode = ProjectionMode::Front;
+        screen.hidden_lines = HiddenLines::Dashed;
+        let runs = &figure_runs(&screen, &figure)[0];
+        assert_eq!(3, runs.len());
+        assert_eq!([true, false, true], [runs[0].visible, runs[1].visible, runs[2].visible]);
+        assert!((runs[1].from - 0.25).abs() < 1e-12 && (runs[1].to - 0.75).abs() < 1e-12);
+
+        // Cached until the figure or the view changes.
+        let mut cache = RunCache::default();
+        assert_eq!(runs, &cache.runs(&screen, &figure)[0]);
+        figure.points[5] = p(0.0, 0.0, 10.0);
+        assert_eq!(2, cache.runs(&screen, &figure)[0].len());
+        screen.mode = ProjectionMode::Top;
+        assert_eq!(1, cache.runs(&screen, &figure)[0].len());
+    }
+
+    #[test]
+    fn pieces() {
+        let figure = cube();
+        let mut screens = default_screens(&Camera::looking_at(&figure));
+        screens[0].mode = ProjectionMode::Isometric;
+        let pieces = |s: &Screen| -> Vec<Piece> {
+            let runs = figure_runs(s, &figure);
+            return figure.edges.iter().zip(runs.iter()).flat_map(|(e, r)| edge_pieces(s, &figure, e, r)).collect();
+        };
+        let count = |s: &Screen| pieces(s).len();
+        assert_eq!(12, count(&screens[0]));
+        screens[0].hidden_lines = HiddenLines::Hide;
+        assert_eq!(9, count(&screens[0]));
+        screens[0].hidden_lines = HiddenLines::Dashed;
+        let dashed = pieces(&screens[0]).iter().filter(|p| p.hidden).count();
+        assert_eq!(3, dashed);
+        for h in HiddenLines::ALL {
+            assert_eq!(Some(h), HiddenLines::from_name(h.name()));
+        }
+    }
+
+    #[test]
+    fn normals() {
+        let square = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        assert_eq!([0.0, 0.0, 1.0], face_normal(&square));
+        assert!(inside(&square, [0.0, 0.0, 1.0], [0.5, 0.5, 0.0]));
+        assert!(!inside(&square, [0.0, 0.0, 1.0], [1.5, 0.5, 0.0]));
+    }
+}
@@ -0,0 +1,80 @@
+use crate::matrix::Matrix;
+use crate::points::{Figure, Point, Scalar};
+
+/// How a 4D figure is lowered to 3D: turned around `center` in the xw, yw and zw planes,
+/// then seen in perspective from `distance` away along w.
+#[derive(Debug, Clone, Copy)]
+pub struct HyperView {
+    pub center: Point<4>,
+    pub distance: f64,
+    /// Rotation angles in radians.
+    pub xw: f64,
+    pub yw: f64,
+    pub zw: f64,
+}
+
+impl HyperView {
+    /// Unrotated view far enough for the whole figure to stay on the near side of the eye.
+    pub fn looking_at<T: Scalar>(figure: &Figure<4, T>) -> HyperView {
+        let center = figure.centroid();
+        let radius = figure.points.iter()
+            .map(|p| (0..4).map(|i| (p.coords[i].to_f64() - center.coords[i]).powi(2)).sum::<f64>().sqrt())
+            .fold(0.0, f64::max);
+        return HyperView { center, distance: (3.0 * radius).max(1.0), xw: 0.0, yw: 0.0, zw: 0.0 };
+    }
+
+    pub fn rotation(&self) -> Matrix<f64, f64, 4> {
+        return Matrix::rotation(2, 3, self.zw) * Matrix::rotation(1, 3, self.yw) * Matrix::rotation(0, 3, self.xw);
+    }
+
+    fn lower_with<T: Scalar>(&self, rotation: &Matrix<f64, f64, 4>, p: &Point<4, T>) -> Point<3> {
+        let relative: Point<4> = Point { coords: [0, 1, 2, 3].map(|i| p.coords[i].to_f64() - self.center.coords[i]) };
+        let r = relative.transform(rotation).coords;
+        let factor = self.distance / (self.distance - r[3]);
+        return Point { coords: [0, 1, 2].map(|i| self.center.coords[i] + r[i] * factor) };
+    }
+
+    /// The 3D shadow of `figure`, edges are kept.
+    pub fn lower_figure<T: Scalar>(&self, figure: &Figure<4, T>) -> Figure<3> {
+        let rotation = self.rotation();
+        return Figure {
+            points: figure.points.iter().map(|p| self.lower_with(&rotation, p)).collect(),
+            edges: figure.edges.clone(),
+            faces: figure.faces.clone(),
+            point_styles: figure.point_styles.clone()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::points::loadFigureN;
+
+    fn tesseract() -> Figure<4> {
+        return loadFigureN(&std::fs::read_to_string("example/tesseract.fig").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn lower() {
+        let figure = tesseract();
+        let view = HyperView::looking_at(&figure);
+        assert_eq!([0.0; 4], view.center.coords);
+        assert_eq!(600.0, view.distance);
+        // The cell nearer to the eye along w is drawn bigger, around the farther one.
+        let near = view.lower_with(&view.rotation(), &Point { coords: [100.0, 100.0, 100.0, 100.0] });
+        let far = view.lower_with(&view.rotation(), &Point { coords: [100.0, 100.0, 100.0, -100.0] });
+        for i in 0..3 {
+            assert!((near.coords[i] - 120.0).abs() < 1e-9);
+            assert!((far.coords[i] - 600.0 / 7.0).abs() < 1e-9);
+        }
+
+        // A quarter turn in the xw plane brings w into x.
+        let turned = HyperView { xw: std::f64::consts::FRAC_PI_2, ..view };
+        let p = turned.lower_with(&turned.rotation(), &Point { coords: [0.0, 0.0, 0.0, 100.0] });
+        assert!((p.coords[0].abs() - 100.0).abs() < 1e-9);
+        let lowered = turned.lower_figure(&figure);
+        assert_eq!(16, lowered.points.len());
+        assert_eq!(figure.edges, lowered.edges);
+    }
+}
@@ -1,28 +1,179 @@
 mod expression;
+mod camera;
+mod hyper;
+mod generators;
+#[cfg(test)]
+mod golden;
+mod hidden;
 mod points;
+mod obj;
+mod parser;
 mod projection;
+mod raster;
+mod shading;
 mod matrix;
+mod files;
+mod cli;
+mod stereo;
+mod svg;
+mod transform;
 
 use std::path::PathBuf;
 use eframe::egui;
-use eframe::egui::{Painter, Context, Id, LayerId, Order};
-use crate::points::{Edge, EdgeAdder, Figure, PointAdder};
-use crate::projection::{Projection, Screen, ToRealScreen};
-use points::Point;
-use eframe::epaint::{ Color32, Stroke };
-use rfd::FileDialog;
-use std::fs;
+use eframe::egui::{Align2, Painter, Context, Id, LayerId, Order, PointerButton, Sense};
+use crate::camera::Camera;
+use crate::generators::{Shape, ShapeParameters};
+use crate::hyper::HyperView;
+use crate::obj::{Rounding, ScalePolicy};
+use crate::points::{AnyFigure, Dash, Edge, EdgeAdder, Error, Figure, Marker, PointAdder};
+use crate::hidden::{edge_pieces, HiddenLines, Run, RunCache};
+use crate::projection::{Clipping, ProjectionMode, Screen, ToRealScreen, CROSS_WIDTH, DASH, DEFAULT_AREA, DOT_SPACING, EDGE_WIDTH, LABEL_SIZE};
+use crate::raster::RasterOptions;
+use crate::shading::{shaded_faces, Fill};
+use crate::stereo::{Anaglyph, StereoLayout, StereoRig};
+use eframe::epaint::{Color32, FontId, Rect, Stroke, Vec2};
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageLevel};
+use std::{env, fs, process};
 
 struct MyWindowHanlder {
     screens: [Screen; 2],
-    figure: Figure<3>
+    /// Figure drawn by the screens, the 3D shadow of `hyper` when a 4D figure is loaded.
+    figure: Figure<3>,
+    hyper: Option<Figure<4>>,
+    hyper_view: HyperView,
+    file: Option<PathBuf>,
+    obj_policy: ScalePolicy,
+    /// Viewpoint between both eyes.
+    camera: Camera,
+    stereo: StereoRig,
+    /// Parameters of the "New" shapes.
+    shape: ShapeParameters,
+    /// Hidden line runs of the figure edges, for each screen.
+    runs: [RunCache; 2]
 }
 
-fn drawEdge(painter: &Painter, s: &Screen, e: &Edge, f: &Figure<3>) {
-    let points = f.edge_points(e);
-    let p1 = s.project(points.0);
-    let p2 = s.project(points.1);
-    painter.line_segment([s.place(&p1),  s.place(&p2)], Stroke::new(2.4, s.color));
+/// Orbit radians per dragged pixel.
+const ROTATION_SPEED: f64 = 0.01;
+/// Dolly factor exponent per scrolled pixel.
+const DOLLY_SPEED: f64 = 0.002;
+
+impl MyWindowHanlder {
+    /// Replaces the figure and frames it.
+    fn show_figure(&mut self, any: AnyFigure) {
+        self.figure = match any {
+            AnyFigure::Three(figure) => {
+                self.hyper = None;
+                figure
+            },
+            AnyFigure::Four(figure) => {
+                self.hyper_view = HyperView::looking_at(&figure);
+                let lowered = self.hyper_view.lower_figure(&figure);
+                self.hyper = Some(figure);
+                lowered
+            }
+        };
+        self.camera = Camera::looking_at(&self.figure);
+    }
+
+    fn save_as(&mut self) {
+        let file: Option<PathBuf> = FileDialog::new()
+            .add_filter("figure", &["fig"])
+            .set_directory("${HOME}")
+            .save_file();
+        if let Some(path) = file {
+            self.save_to(path);
+        }
+    }
+
+    fn save(&mut self) {
+        match self.file.clone() {
+            Some(path) => self.save_to(path),
+            None => self.save_as()
+        }
+    }
+
+    fn save_to(&mut self, path: PathBuf) {
+        let data = match &self.hyper {
+            Some(hyper) => points::saveFigure(hyper),
+            None => points::saveFigure(&self.figure)
+        };
+        match fs::write(&path, data) {
+            Ok(()) => self.file = Some(path),
+            Err(e) => show_error("Unable to save figure", &Error { cause: e.to_string() }),
+        }
+    }
+}
+
+fn drawEdge(painter: &Painter, s: &Screen, e: &Edge, f: &Figure<3>, runs: &[Run]) {
+    let stroke = Stroke::new(e.style.width.unwrap_or(EDGE_WIDTH), s.tint(e.style.color32()));
+    for piece in edge_pieces(s, f, e, runs) {
+        let ends = [s.place(&piece.ends[0]), s.place(&piece.ends[1])];
+        match piece.dash(e) {
+            Dash::Solid => painter.line_segment(ends, stroke),
+            Dash::Dashed => painter.extend(egui::Shape::dashed_line(&ends, stroke, DASH[0], DASH[1])),
+            Dash::Dotted => painter.extend(egui::Shape::dotted_line(&ends, stroke.color, DOT_SPACING, stroke.width / 2.0)),
+        }
+    }
+}
+
+/// Markers and labels of the points that have some.
+fn drawPoints(painter: &Painter, s: &Screen, f: &Figure<3>) {
+    let color = s.tint(Color32::WHITE);
+    for (index, style) in f.point_styles.iter() {
+        let at = match s.visible_point(&f.points[*index]) {
+            Some(p) => s.place(&p),
+            None => continue
+        };
+        let half = style.size / 2.0;
+        match style.marker {
+            Some(Marker::Circle) => painter.circle_filled(at, half, color),
+            Some(Marker::Square) => painter.rect_filled(Rect::from_center_size(at, Vec2::splat(style.size)), 0.0, color),
+            Some(Marker::Cross) => {
+                let stroke = Stroke::new(CROSS_WIDTH, color);
+                painter.line_segment([at + Vec2::new(-half, -half), at + Vec2::new(half, half)], stroke);
+                painter.line_segment([at + Vec2::new(-half, half), at + Vec2::new(half, -half)], stroke);
+            },
+            None => {}
+        }
+        if let Some(label) = &style.label {
+            painter.text(at + Vec2::new(half + 2.0, -half - 2.0), Align2::LEFT_BOTTOM, label,
+                         FontId::proportional(LABEL_SIZE), color);
+        }
+    }
+}
+
+/// Shaded faces, back to front, then the edges, as the fill mode of the screen asks, then the point markers.
+/// `runs` are the visible and hidden runs of the edges.
+fn drawScreen(painter: &Painter, s: &Screen, f: &Figure<3>, runs: &[Vec<Run>]) {
+    let painter = painter.with_clip_rect(s.viewport());
+    if s.shading.fill.faces() {
+        for polygon in shaded_faces(s, f) {
+            let points = polygon.points.iter().map(|p| s.place(p)).collect();
+            painter.add(egui::Shape::convex_polygon(points, polygon.color, Stroke::none()));
+        }
+    }
+    if s.shading.fill.edges() {
+        f.edges.iter().zip(runs.iter()).for_each(|(e, r)| drawEdge(&painter, s, e, f, r));
+    }
+    drawPoints(&painter, s, f);
+}
+
+fn show_error(title: &str, error: &Error) {
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title(title)
+        .set_description(error.cause.as_str())
+        .set_buttons(MessageButtons::Ok)
+        .show();
+}
+
+fn show_warning(title: &str, message: &str) {
+    MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title(title)
+        .set_description(message)
+        .set_buttons(MessageButtons::Ok)
+        .show();
 }
 
 impl eframe::App for MyWindowHanlder {
@@ -34,37 +185,212 @@ impl eframe::App for MyWindowHanlder {
                 let painter =
                     ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("graphics")));
 
-                let window = &self;
-                let s1 :&Screen = &window.screens[0];
+                // Left drag orbits around the target, right or middle drag pans, scrolling dollies.
+                let response = ui.allocate_response(ui.available_size(), Sense::drag());
+                let delta = response.drag_delta();
+                let shift = ui.input().modifiers.shift;
+                if response.dragged_by(PointerButton::Primary) && shift && self.hyper.is_some() {
+                    // Shift drag turns 4D figures in the xw and yw planes.
+                    self.hyper_view.xw += delta.x as f64 * ROTATION_SPEED;
+                    self.hyper_view.yw += delta.y as f64 * ROTATION_SPEED;
+                } else if response.dragged_by(PointerButton::Primary) {
+                    self.camera.orbit(delta.x as f64 * ROTATION_SPEED, delta.y as f64 * ROTATION_SPEED);
+                } else if response.dragged_by(PointerButton::Secondary) || response.dragged_by(PointerButton::Middle) {
+                    let pixel = self.camera.pixel_size(self.screens[0].size[1]);
+                    self.camera.pan(-delta.x as f64 * pixel, delta.y as f64 * pixel);
+                }
+                if response.hovered() {
+                    let scroll = ui.input().scroll_delta.y as f64;
+                    if scroll != 0.0 {
+                        self.camera.dolly((-scroll * DOLLY_SPEED).exp());
+                    }
+                }
+                self.stereo.apply(&mut self.screens, &self.camera, DEFAULT_AREA);
+                if let Some(hyper) = &self.hyper {
+                    self.figure = self.hyper_view.lower_figure(hyper);
+                }
 
-                window.figure.edges
-                    .iter()
-                    .for_each(|e: &Edge| -> () {
-                        drawEdge(&painter, s1, e, &window.figure);
-                    });
-                let s2 :&Screen = &window.screens[1];
-                window.figure.edges.iter().for_each(|e| -> () {
-                    drawEdge(&painter, s2, e, &window.figure);
-                });
+                for (s, cache) in self.screens.iter().zip(self.runs.iter_mut()) {
+                    drawScreen(&painter, s, &self.figure, cache.runs(s, &self.figure));
+                }
 
                 egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
                     // The top panel is often a good place for a menu bar:
                     egui::menu::bar(ui, |ui| {
                         ui.menu_button("File", |ui| {
+                            ui.menu_button("New", |ui| {
+                                let shape = &mut self.shape;
+                                ui.horizontal(|ui| {
+                                    ui.label("Size");
+                                    ui.add(egui::DragValue::new(&mut shape.size).speed(1.0).clamp_range(1.0..=10000.0));
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Subdivisions");
+                                    ui.add(egui::DragValue::new(&mut shape.subdivisions).clamp_range(1..=256));
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Dimension");
+                                    ui.radio_value(&mut shape.dimension, 3, "3D");
+                                    ui.radio_value(&mut shape.dimension, 4, "4D");
+                                });
+                                ui.separator();
+                                for s in Shape::ALL {
+                                    if ui.button(s.name()).clicked() {
+                                        match generators::generate(s, &self.shape) {
+                                            Ok(any) => {
+                                                self.show_figure(any);
+                                                self.file = None;
+                                            },
+                                            Err(e) => show_error("Unable to create shape", &e),
+                                        }
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
                             if ui.button("Load").clicked() {
                                 let files: Option<PathBuf> = FileDialog::new()
-                                    .add_filter("figure", &["fig"])
+                                    .add_filter("figure", &["fig", "obj"])
                                     .set_directory("${HOME}")
                                     .pick_file();
-                                let path: PathBuf = files.unwrap();
-                                let data = fs::read_to_string(path).expect("Unable to read file");
-                                println!("content = {}", data);
-                                points::loadFigure(data.as_str(), &mut self.figure);
+                                if let Some(path) = files {
+                                    match files::read_any_figure(&path, &self.obj_policy) {
+                                        Ok(any) => {
+                                            let issues: Vec<String> = any.validate()
+                                                .iter()
+                                                .filter(|i| !i.is_fatal())
+                                                .map(|i| i.to_string())
+                                                .collect();
+                                            if !issues.is_empty() {
+                                                show_warning("Figure loaded with issues", &issues.join("\n"));
+                                            }
+                                            self.show_figure(any);
+                                            // Only .fig files can be saved back in place.
+                                            self.file = match path.extension() {
+                                                Some(e) if e == "fig" => Some(path),
+                                                _ => None
+                                            };
+                                        },
+                                        Err(e) => show_error("Unable to load figure", &e),
+                                    }
+                                }
+                            }
+                            ui.menu_button("OBJ import", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("Scale");
+                                    ui.add(egui::DragValue::new(&mut self.obj_policy.scale).speed(0.1));
+                                });
+                                ui.radio_value(&mut self.obj_policy.rounding, Rounding::None, "Keep decimals");
+                                ui.radio_value(&mut self.obj_policy.rounding, Rounding::Nearest, "Round to nearest");
+                                ui.radio_value(&mut self.obj_policy.rounding, Rounding::Floor, "Round down");
+                                ui.radio_value(&mut self.obj_policy.rounding, Rounding::Ceil, "Round up");
+                                ui.radio_value(&mut self.obj_policy.rounding, Rounding::Truncate, "Truncate");
+                            });
+                            if ui.button("Save").clicked() {
+                                self.save();
+                            }
+                            if ui.button("Save As").clicked() {
+                                self.save_as();
+                            }
+                            if ui.button("Export SVG").clicked() {
+                                let file: Option<PathBuf> = FileDialog::new()
+                                    .add_filter("svg", &["svg"])
+                                    .set_directory("${HOME}")
+                                    .save_file();
+                                if let Some(path) = file {
+                                    if let Err(e) = svg::write_svg(&path, &self.figure, &self.screens) {
+                                        show_error("Unable to export figure", &Error { cause: e.to_string() });
+                                    }
+                                }
+                            }
+                            if ui.button("Export PNG").clicked() {
+                                let file: Option<PathBuf> = FileDialog::new()
+                                    .add_filter("png", &["png"])
+                                    .set_directory("${HOME}")
+                                    .save_file();
+                                if let Some(path) = file {
+                                    let options = RasterOptions::default();
+                                    if let Err(e) = raster::write_png(&path, &self.figure, &self.screens, &options) {
+                                        show_error("Unable to export figure", &Error { cause: e.to_string() });
+                                    }
+                                }
                             }
                             if ui.button("Quit").clicked() {
                                 _frame.close();
                             }
                         });
+                        ui.menu_button("View", |ui| {
+                            for (s, title) in self.screens.iter_mut().zip(["Left screen", "Right screen"]) {
+                                ui.menu_button(title, |ui| {
+                                    for m in ProjectionMode::ALL {
+                                        ui.radio_value(&mut s.mode, m, m.name());
+                                    }
+                                });
+                            }
+                            ui.separator();
+                            let mut hidden = self.screens[0].hidden_lines;
+                            for h in HiddenLines::ALL {
+                                ui.radio_value(&mut hidden, h, format!("{} hidden lines", h.name()));
+                            }
+                            for s in self.screens.iter_mut() {
+                                s.hidden_lines = hidden;
+                            }
+                            ui.separator();
+                            let mut shading = self.screens[0].shading;
+                            for f in Fill::ALL {
+                                ui.radio_value(&mut shading.fill, f, f.name());
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("Light from");
+                                for (value, axis) in shading.light.iter_mut().zip(["x ", "y ", "z "]) {
+                                    ui.add(egui::DragValue::new(value).speed(0.05).prefix(axis));
+                                }
+                            });
+                            for s in self.screens.iter_mut() {
+                                s.shading = shading;
+                            }
+                            ui.separator();
+                            let mut frustum = self.screens[0].clipping == Clipping::Frustum;
+                            if ui.checkbox(&mut frustum, "Clip to field of view").changed() {
+                                for s in self.screens.iter_mut() {
+                                    s.clipping = if frustum { Clipping::Frustum } else { Clipping::Near };
+                                }
+                            }
+                        });
+                        if self.hyper.is_some() {
+                            ui.menu_button("4D", |ui| {
+                                let view = &mut self.hyper_view;
+                                let turn = -std::f64::consts::PI..=std::f64::consts::PI;
+                                ui.add(egui::Slider::new(&mut view.xw, turn.clone()).text("xw rotation"));
+                                ui.add(egui::Slider::new(&mut view.yw, turn.clone()).text("yw rotation"));
+                                ui.add(egui::Slider::new(&mut view.zw, turn).text("zw rotation"));
+                                ui.label("Shift drag turns in the xw and yw planes.");
+                                if ui.button("Reset").clicked() {
+                                    view.xw = 0.0;
+                                    view.yw = 0.0;
+                                    view.zw = 0.0;
+                                }
+                            });
+                        }
+                        ui.menu_button("Stereo", |ui| {
+                            let rig = &mut self.stereo;
+                            let mut changed = ui.add(egui::Slider::new(&mut rig.interocular, 0.0..=200.0)
+                                .text("Eye distance")).changed();
+                            changed |= ui.add(egui::Slider::new(&mut rig.convergence, -300.0..=300.0)
+                                .text("Convergence")).changed();
+                            ui.separator();
+                            for l in StereoLayout::ALL {
+                                changed |= ui.radio_value(&mut rig.layout, l, l.name()).changed();
+                            }
+                            if rig.layout == StereoLayout::Overlay {
+                                ui.separator();
+                                for a in Anaglyph::ALL {
+                                    changed |= ui.radio_value(&mut rig.anaglyph, a, a.name()).changed();
+                                }
+                            }
+                            if changed {
+                                rig.apply(&mut self.screens, &self.camera, DEFAULT_AREA);
+                            }
+                        });
                     });
                 });
             }
@@ -77,30 +403,25 @@ impl eframe::App for MyWindowHanlder {
 }
 
 fn main() {
-    const s1: Screen = Screen {
-        zoom: 150,
-        projectionpos: -250,
-        center: Point { coords: [-30, 0] },
-        color: Color32::RED,
-        size: [900, 700],
-    };
-    const s2: Screen = Screen {
-        zoom: 150,
-        projectionpos: -250,
-        center: Point { coords: [30, 0] },
-        color: Color32::BLUE,
-        size: [900, 700],
-    };
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 {
+        process::exit(cli::run(&args[1..]));
+    }
 
     let handler: MyWindowHanlder = MyWindowHanlder {
-        screens: [s1, s2],
-        figure: Figure {
-            points: vec![],
-            edges: vec![] }
+        screens: projection::default_screens(&Camera::default()),
+        figure: Figure::new(),
+        hyper: None,
+        hyper_view: HyperView::looking_at::<f64>(&Figure::new()),
+        file: None,
+        obj_policy: ScalePolicy::default(),
+        camera: Camera::default(),
+        stereo: StereoRig::default(),
+        shape: ShapeParameters::default(),
+        runs: Default::default()
     };
    // let application = gtk::Application::new(Some("The.name.goes.here"), Default::default())
     //    .expect("Initialization failed");
-    println!("Hello, world!");
 
     let options = eframe::NativeOptions::default();
     eframe::run_native(
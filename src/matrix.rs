@@ -1,19 +1,106 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::ops::{Add, Mul};
 use std::marker::{Copy, PhantomData};
 use crate::expression::Expression::Expr;
+use crate::points::Error;
 
 
 pub trait Group : Add<Self> + Copy + Clone {
     fn zero() -> Self;
 
     fn minus(&self) -> Self;
+
+    /// Sum, None when it overflows. Types without overflow always have one.
+    fn checked_add(self, other: Self) -> Option<Self> where Self: Add<Output = Self> {
+        return Some(self + other);
+    }
 }
 
 pub trait Ring : Group + Mul<Self> {
     fn neutral() -> Self;
 
-    fn inverse(&self) -> Self;
+    /// None when the element has no multiplicative inverse.
+    fn inverse(&self) -> Option<Self>;
+
+    /// True when the element can't be told from zero next to elements as big as `scale`.
+    /// Exact rings neglect nothing, their zero has no inverse anyway.
+    fn negligible(&self, _scale: Self) -> bool {
+        return false;
+    }
+
+    /// Product, None when it overflows. Types without overflow always have one.
+    fn checked_mul(self, other: Self) -> Option<Self> where Self: Mul<Output = Self> {
+        return Some(self * other);
+    }
+}
+
+/// Values smaller than this share of the terms they were computed from are not taken as pivots,
+/// to keep rounding noise from passing as one.
+pub const EPSILON: f64 = 1e-10;
+
+impl Group for f64 {
+    fn zero() -> Self {
+        return 0.0;
+    }
+
+    fn minus(&self) -> Self {
+        return -self;
+    }
+}
+
+impl Ring for f64 {
+    fn neutral() -> Self {
+        return 1.0;
+    }
+
+    fn inverse(&self) -> Option<Self> {
+        let inverse = 1.0 / self;
+        if inverse.is_finite() {
+            Some(inverse)
+        } else {
+            None
+        }
+    }
+
+    fn negligible(&self, scale: Self) -> bool {
+        return self.abs() <= EPSILON * scale.abs();
+    }
+}
+
+/// Integers, the constants of symbolic expressions.
+impl Group for i32 {
+    fn zero() -> Self {
+        return 0;
+    }
+
+    fn minus(&self) -> Self {
+        return -self;
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        return i32::checked_add(self, other);
+    }
+}
+
+impl Ring for i32 {
+    fn neutral() -> Self {
+        return 1;
+    }
+
+    /// Only 1 and -1 are units.
+    fn inverse(&self) -> Option<Self> {
+        if self.abs() == 1 {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        return i32::checked_mul(self, other);
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -27,6 +114,16 @@ pub trait MatrixElement<T : Ring> : Ring {
     fn multiply(&self, r: Self) -> Self;
 }
 
+impl MatrixElement<f64> for f64 {
+    fn mult(&self, x: f64) -> f64 {
+        return self * x;
+    }
+
+    fn multiply(&self, r: Self) -> Self {
+        return self * r;
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Matrix<T, U, const N: usize> where U : MatrixElement<T>, T : Ring {
     pub elements : [Vector<U, N>; N],
@@ -87,13 +184,13 @@ impl<T : Ring + Add<Output = T> + Mul<Output = T>, const N: usize> Ring for Vect
         return result;
     }
 
-    fn inverse(&self) -> Self {
+    fn inverse(&self) -> Option<Self> {
         let mut p: Vector<T, N> = Self::neutral();
 
         for i  in 0..p.values.len() {
-            p.values[i] = self.values[i].inverse();
+            p.values[i] = self.values[i].inverse()?;
         }
-        return p;
+        return Some(p);
     }
 }
 
@@ -125,7 +222,11 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Outp
     }
 
     fn minus(&self) -> Self {
-        return *self; // todo!()
+        let mut result: Matrix<T, U, N> = *self;
+        for line in 0..N {
+            result.elements[line] = self.elements[line].minus();
+        }
+        return result;
     }
 }
 
@@ -137,7 +238,7 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Outp
         for line in 0..N {
             for col in 0.. N {
                 for index in 0..N {
-                    result.elements[col].values[line] = result.elements[col].values[line]
+                    result.elements[line].values[col] = result.elements[line].values[col]
                         + self.elements[line].values[index]*rhs.elements[index].values[col];
                 }
             }
@@ -146,7 +247,158 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Outp
     }
 }
 
-impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Output = U> + Mul<Output = U>, const N: usize> Ring for Matrix<T, U, N> {
+impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Output = U> + Mul<Output = U>, const N: usize> Matrix<T, U, N> {
+    pub fn new(elements: [Vector<U, N>; N]) -> Self {
+        return Matrix {
+            elements,
+            _t: Default::default()
+        }
+    }
+
+    /// Matrix product with a column vector.
+    pub fn apply(&self, v: &Vector<T, N>) -> Vector<T, N> {
+        let mut result: Vector<T, N> = Vector::zero();
+        for line in 0..N {
+            for col in 0..N {
+                result.values[line] = result.values[line] + self.elements[line].values[col].mult(v.values[col]);
+            }
+        }
+        return result;
+    }
+}
+
+impl<const N: usize> Matrix<f64, f64, N> {
+    /// Rotation of `angle` radians in the plane of axes `from` and `to`, turning `from` toward `to`.
+    pub fn rotation(from: usize, to: usize, angle: f64) -> Self {
+        let mut result: Matrix<f64, f64, N> = Matrix::neutral();
+        let (sin, cos) = angle.sin_cos();
+        result.elements[from].values[from] = cos;
+        result.elements[from].values[to] = -sin;
+        result.elements[to].values[from] = sin;
+        result.elements[to].values[to] = cos;
+        return result;
+    }
+
+    pub fn rotation_x(angle: f64) -> Self {
+        return Self::rotation(1, 2, angle);
+    }
+
+    pub fn rotation_y(angle: f64) -> Self {
+        return Self::rotation(2, 0, angle);
+    }
+
+    pub fn rotation_z(angle: f64) -> Self {
+        return Self::rotation(0, 1, angle);
+    }
+
+    pub fn scaling(factors: [f64; N]) -> Self {
+        let mut result: Matrix<f64, f64, N> = Matrix::neutral();
+        for i in 0..N {
+            result.elements[i].values[i] = factors[i];
+        }
+        return result;
+    }
+}
+
+/// Homogeneous coordinates for 3D affine transforms.
+impl Matrix<f64, f64, 4> {
+    pub fn affine(linear: &Matrix<f64, f64, 3>, translation: [f64; 3]) -> Self {
+        let mut result: Matrix<f64, f64, 4> = Matrix::neutral();
+        for line in 0..3 {
+            for col in 0..3 {
+                result.elements[line].values[col] = linear.elements[line].values[col];
+            }
+            result.elements[line].values[3] = translation[line];
+        }
+        return result;
+    }
+
+    pub fn translation(translation: [f64; 3]) -> Self {
+        return Self::affine(&Matrix::neutral(), translation);
+    }
+}
+
+impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Output = U> + Mul<Output = U> + PartialOrd, const N: usize> Matrix<T, U, N> {
+
+    fn abs(v: U) -> U {
+        if v < U::zero() { v.minus() } else { v }
+    }
+
+    /// Magnitudes of the elements. Elimination raises each to the biggest term added into the
+    /// element: values negligible next to theirs are the rounding noise of a cancellation.
+    fn magnitudes(&self) -> [[U; N]; N] {
+        return self.elements.map(|line| line.values.map(Self::abs));
+    }
+
+    /// Subtracts `factor` times the row `from` from the row `to`.
+    fn subtract_row(&mut self, to: usize, from: usize, factor: U) {
+        for c in 0..N {
+            self.elements[to].values[c] = self.elements[to].values[c] + (factor * self.elements[from].values[c]).minus();
+        }
+    }
+
+    /// Magnitudes of the row `to` once `subtract_row` has added the terms of the row `from` to it.
+    fn raise_sizes(&self, sizes: &mut [[U; N]; N], to: usize, from: usize, factor: U) {
+        for c in 0..N {
+            let term = Self::abs(factor * self.elements[from].values[c]);
+            if term > sizes[to][c] {
+                sizes[to][c] = term;
+            }
+        }
+    }
+
+    /// True when the value at `line`, `col` is zero or rounding noise.
+    fn vanishes(&self, line: usize, col: usize, sizes: &[[U; N]; N]) -> bool {
+        let v = self.elements[line].values[col];
+        return v == U::zero() || v.negligible(sizes[line][col]);
+    }
+
+    /// Row, from `start`, holding the invertible value of `col` with the biggest magnitude.
+    /// Values that vanish next to their `sizes` are left out.
+    fn pivot(&self, col: usize, start: usize, sizes: &[[U; N]; N]) -> Option<usize> {
+        let mut pivot: Option<usize> = None;
+        for line in start..N {
+            let v = self.elements[line].values[col];
+            if v.inverse().is_some() && !self.vanishes(line, col, sizes)
+                && pivot.map_or(true, |p| Self::abs(v) > Self::abs(self.elements[p].values[col])) {
+                pivot = Some(line);
+            }
+        }
+        return pivot;
+    }
+
+    /// Gaussian elimination with partial pivoting, zero for singular matrices.
+    /// Over rings that are not fields, such as the integers, it is None when a column still has
+    /// values but none of them is a unit to pivot on.
+    pub fn determinant(&self) -> Option<U> {
+        let mut m: Matrix<T, U, N> = *self;
+        let mut sizes = self.magnitudes();
+        let mut det: U = U::neutral();
+        for col in 0..N {
+            let pivot = match m.pivot(col, col, &sizes) {
+                Some(p) => p,
+                None if (col..N).all(|line| m.vanishes(line, col, &sizes)) => return Some(U::zero()),
+                None => return None
+            };
+            if pivot != col {
+                m.elements.swap(pivot, col);
+                sizes.swap(pivot, col);
+                det = det.minus();
+            }
+            let p = m.elements[col].values[col];
+            det = det * p;
+            let inv = p.inverse().unwrap();
+            for line in col + 1..N {
+                let factor = m.elements[line].values[col] * inv;
+                m.raise_sizes(&mut sizes, line, col, factor);
+                m.subtract_row(line, col, factor);
+            }
+        }
+        return Some(det);
+    }
+}
+
+impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Output = U> + Mul<Output = U> + PartialOrd, const N: usize> Ring for Matrix<T, U, N> {
     fn neutral() -> Self {
         let mut result : [Vector<U, N>; N] = [Vector {
             values: [U::zero(); N]
@@ -160,8 +412,33 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T>, U: MatrixElement<T> + Add<Outp
         }
     }
 
-    fn inverse(&self) -> Self {
-        return *self; // todo!()
+    /// Gauss-Jordan elimination with partial pivoting, None for singular matrices.
+    fn inverse(&self) -> Option<Self> {
+        let mut m: Matrix<T, U, N> = *self;
+        let mut result: Matrix<T, U, N> = Self::neutral();
+        let mut sizes = self.magnitudes();
+        for col in 0..N {
+            let pivot = m.pivot(col, col, &sizes)?;
+            m.elements.swap(pivot, col);
+            sizes.swap(pivot, col);
+            result.elements.swap(pivot, col);
+
+            let inv = m.elements[col].values[col].inverse()?;
+            for c in 0..N {
+                m.elements[col].values[c] = inv * m.elements[col].values[c];
+                sizes[col][c] = Self::abs(inv) * sizes[col][c];
+                result.elements[col].values[c] = inv * result.elements[col].values[c];
+            }
+            for line in 0..N {
+                if line != col {
+                    let factor = m.elements[line].values[col];
+                    m.raise_sizes(&mut sizes, line, col, factor);
+                    m.subtract_row(line, col, factor);
+                    result.subtract_row(line, col, factor);
+                }
+            }
+        }
+        return Some(result);
     }
 }
 
@@ -176,6 +453,44 @@ pub enum Function {
 }
 
 impl Function {
+    pub const ALL: [Function; 6] = [Function::Sum, Function::Sub, Function::Product, Function::Div, Function::Cos, Function::Sin];
+
+    /// Name in the call syntax of the parser, as in `sum(x, 1)`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Sum => "sum",
+            Function::Sub => "sub",
+            Function::Product => "product",
+            Function::Div => "div",
+            Function::Cos => "cos",
+            Function::Sin => "sin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Function> {
+        return Function::ALL.iter().find(|f| f.name() == name).copied();
+    }
+
+    /// Infix operator and its precedence, higher binding tighter.
+    pub fn operator(&self) -> Option<(char, u8)> {
+        match self {
+            Function::Sum => Some(('+', 1)),
+            Function::Sub => Some(('-', 1)),
+            Function::Product => Some(('*', 2)),
+            Function::Div => Some(('/', 2)),
+            Function::Cos | Function::Sin => None,
+        }
+    }
+
+    /// Number of arguments taken, None when any number is.
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            Function::Sum | Function::Product => None,
+            Function::Sub | Function::Div => Some(2),
+            Function::Cos | Function::Sin => Some(1),
+        }
+    }
+
     pub fn calc(&self, args: Vec<f64>) -> f64 {
         match self {
             Function::Sum => { args.iter().sum() }
@@ -211,9 +526,28 @@ impl Function {
     }
 }
 
+/// Values of the variables of expressions.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    pub values: BTreeMap<String, f64>,
+}
+
+impl Environment {
+    pub fn bind(mut self, name: &str, value: f64) -> Self {
+        self.values.insert(name.to_string(), value);
+        return self;
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        return self.values.get(name).copied();
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub enum Expression<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq> {
     Cons(T),
+    /// Named variable, valued by an `Environment`.
+    Var(String),
     Exp{ f: Function, arguments: Vec<Expression<T>> }
 }
 
@@ -223,6 +557,9 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq + Ord> Ord for
             (Expression::Cons(x), Expression::Cons(y)) => x.cmp(y),
             (Expression::Cons(_), _) => Ordering::Less,
             (_, Expression::Cons(_)) => Ordering::Greater,
+            (Expression::Var(x), Expression::Var(y)) => x.cmp(y),
+            (Expression::Var(_), _) => Ordering::Less,
+            (_, Expression::Var(_)) => Ordering::Greater,
             (Expression::Exp {f:f1, arguments:args1},
                 Expression::Exp {f:f2, arguments:args2})
                 => {
@@ -252,16 +589,77 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq> Clone for Exp
     fn clone(&self) -> Self {
         match self {
             Expression::Cons(x) => Expression::Cons(x.clone()),
+            Expression::Var(name) => Expression::Var(name.clone()),
             Expression::Exp {f: f1, arguments: arguments1}
                => Expression::Exp {f : *f1, arguments: arguments1.clone() }
         }
     }
 }
 
+impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq> Expression<T> {
+    /// Operator and precedence of an expression written infix, only when it has the arguments that requires.
+    fn infix(&self) -> Option<(char, u8)> {
+        return match self {
+            Expression::Exp { f, arguments } => match f.arity() {
+                Some(n) if n != arguments.len() => None,
+                None if arguments.len() < 2 => None,
+                _ => f.operator()
+            },
+            _ => None
+        }
+    }
+}
+
+/// Infix text read back by `parser::parse_expression` as the same expression.
+/// Functions without operator, and operators without the usual number of arguments, use the call syntax.
+impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq + fmt::Display> fmt::Display for Expression<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self, self.infix()) {
+            (Expression::Cons(x), _) => write!(f, "{}", x),
+            (Expression::Var(name), _) => write!(f, "{}", name),
+            (Expression::Exp { f: function, arguments }, Some((operator, precedence))) => {
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " {} ", operator)?;
+                    }
+                    // Operators are left associative, and the parser gathers repeated sums and products.
+                    let gathered = matches!(argument, Expression::Exp { f: inner, .. } if inner == function && function.arity().is_none());
+                    let parenthesized = match argument.infix() {
+                        Some((_, p)) if i == 0 => p < precedence || gathered,
+                        Some((_, p)) => p <= precedence,
+                        None => false
+                    };
+                    if parenthesized {
+                        write!(f, "({})", argument)?;
+                    } else {
+                        write!(f, "{}", argument)?;
+                    }
+                }
+                Ok(())
+            },
+            (Expression::Exp { f: function, arguments }, None) => {
+                write!(f, "{}(", function.name())?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
 impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq + Ord> Expression<T> {
+    pub fn var(name: &str) -> Expression<T> {
+        return Expression::Var(name.to_string());
+    }
+
     pub fn simplify(&self) -> Expression<T> {
         return match self {
             Self::Cons(x) => Self::Cons(*x),
+            Self::Var(name) => Self::Var(name.clone()),
             Self::Exp{f, arguments} => Expression::simplify_call(*f, arguments)
         }
     }
@@ -271,101 +669,100 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq + Ord> Express
             Function::Sum => Self::simplify_add(arguments_in),
             Function::Sub => Self::simplify_sub(arguments_in),
             Function::Product => Self::simplify_mul(arguments_in),
+            Function::Div => Self::simplify_div(arguments_in),
             Function::Cos => Self::simplify_cos(arguments_in),
-            _ => Self::Exp{ f : f_in, arguments: arguments_in.clone() }
+            Function::Sin => Self::simplify_sin(arguments_in),
+        }
+    }
+
+    /// Constant factor of a simplified term and the product of its other factors.
+    fn split_coefficient(self) -> (T, Expression<T>) {
+        if let Expression::Exp { f: Function::Product, arguments } = &self {
+            if let [Expression::Cons(c), rest @ ..] = arguments.as_slice() {
+                let rest = if rest.len() == 1 {
+                    rest[0].clone()
+                } else {
+                    Expression::Exp { f: Function::Product, arguments: rest.to_vec() }
+                };
+                return (*c, rest);
+            }
+        }
+        return (T::neutral(), self);
+    }
+
+    /// Inverse of `split_coefficient`.
+    fn with_coefficient(coefficient: T, term: Expression<T>) -> Expression<T> {
+        if coefficient == T::neutral() {
+            return term;
         }
+        let mut factors = match term {
+            Expression::Exp { f: Function::Product, arguments } => arguments,
+            other => vec![other]
+        };
+        factors.insert(0, Expression::Cons(coefficient));
+        return Expression::Exp { f: Function::Product, arguments: factors };
     }
 
+    /// Flattens nested sums, adds the constants and collects the terms differing only by a constant factor.
+    /// Constants whose sum overflows are left apart.
     fn simplify_add(arguments: &Vec<Expression<T>>) -> Expression<T> {
-        if arguments.len() == 1 {
-            return arguments[0].simplify();
-        }
-
-        // Add all cons
-        let mut args : Vec<Expression<T>> = arguments.to_vec();
-        let mut index1 : usize = 0;
-        let mut first : usize = 0;
-        let mut start_ok: bool = false;
-        let mut sum : T = T::zero();
-        while index1 < args.len() {
-            args[index1] = args[index1].simplify();
-            let arg_indexed = args[index1].clone();
-            match arg_indexed {
-                Expression::Cons(v1) => {
-                    sum = sum + v1;
-                    if !start_ok {
-                        start_ok = true;
-                        first = index1;
-                        index1 += 1;
-                    }
-                    else {
-                        args.remove(index1);
-                    }
+        let mut constant: T = T::zero();
+        let mut unfolded: Vec<Expression<T>> = vec![];
+        let mut terms: Vec<(T, Expression<T>)> = vec![];
+        let mut pending: Vec<Expression<T>> = arguments.iter().map(|a| a.simplify()).collect();
+        while let Some(arg) = pending.pop() {
+            match arg {
+                Expression::Cons(v) => match constant.checked_add(v) {
+                    Some(sum) => constant = sum,
+                    None => unfolded.push(Expression::Cons(v))
                 },
-                Expression::Exp { f: Function::Sum, arguments:args_sum} => {
-                    for a in args_sum {
-                        args.push(a.clone())
+                Expression::Exp { f: Function::Sum, arguments: inner } => pending.extend(inner),
+                term => {
+                    let (coefficient, rest) = term.split_coefficient();
+                    let collected = terms.iter_mut()
+                        .find(|(c, r)| *r == rest && c.checked_add(coefficient).is_some());
+                    match collected {
+                        Some((c, _)) => *c = *c + coefficient,
+                        None => terms.push((coefficient, rest))
                     }
-                },
-                _ => index1 += 1
+                }
             }
         }
-        if start_ok {
-            args[first] = Expression::Cons(sum);
+        let mut args: Vec<Expression<T>> = unfolded;
+        args.extend(terms.into_iter()
+            .filter(|(c, _)| *c != T::zero())
+            .map(|(c, rest)| Self::with_coefficient(c, rest)));
+        if constant != T::zero() || args.is_empty() {
+            args.push(Expression::Cons(constant));
         }
-        if arguments.len() == 1 {
-            return arguments[0].simplify();
+        if args.len() == 1 {
+            return args.pop().unwrap();
         }
         args.sort();
-
         return Self::Exp{ f: Function::Sum, arguments: args };
     }
 
+    /// Flattens nested products and multiplies the constants, put first.
+    /// Constants whose product overflows are left apart.
     fn simplify_mul(arguments: &Vec<Expression<T>>) -> Expression<T> {
-        if arguments.len() == 1 {
-            return arguments[0].simplify();
-        }
-
-        // Add all cons
-        let mut args : Vec<Expression<T>> = arguments.to_vec();
-        let mut index1 : usize = 0;
-        let mut first : usize = 0;
-        let mut start_ok: bool = false;
         let mut product: T = T::neutral();
-        while index1 < args.len() {
-            args[index1] = args[index1].simplify();
-            let arg_indexed = args[index1].clone();
-            match arg_indexed {
-                Expression::Cons(v1) => {
-                    product = product * v1;
-                    if !start_ok {
-                        start_ok = true;
-                        first = index1;
-                        index1 += 1;
-                    }
-                    else {
-                        args.remove(index1);
-                    }
+        let mut args: Vec<Expression<T>> = vec![];
+        let mut pending: Vec<Expression<T>> = arguments.iter().map(|a| a.simplify()).collect();
+        while let Some(arg) = pending.pop() {
+            match arg {
+                Expression::Cons(v) => match product.checked_mul(v) {
+                    Some(p) => product = p,
+                    None => args.push(Expression::Cons(v))
                 },
-                Expression::Exp { f: Function::Product, arguments:args_product} => {
-                    for a in args_product {
-                        args.push(a.clone())
-                    }
-                },
-                _ => index1 += 1
+                Expression::Exp { f: Function::Product, arguments: inner } => pending.extend(inner),
+                factor => args.push(factor)
             }
         }
         if product == T::zero() {
             return Expression::Cons(T::zero());
         }
-        if start_ok {
-            args[first] = Expression::Cons(product);
-        }
-        if arguments.len() == 1 {
-            return arguments[0].simplify();
-        }
         args.sort();
-        index1 = 0;
+        let mut index1: usize = 0;
         while index1 + 1 < args.len() {
             let f1 = &args[index1];
             let f2 = &args[index1 + 1];
@@ -385,51 +782,128 @@ impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq + Ord> Express
                             Expression::Exp{ f:Function::Sum, arguments:vec![Expression::Cons(T::neutral()),
                                                                              Expression::Cons(T::neutral())]}.simplify()
                         ]};
-                    args[index1] = res;
+                    args[index1] = res.simplify();
                     args.remove(index1 + 1);
                 },
                 (_, _) => index1 += 1
             }
         }
+        if product != T::neutral() || args.is_empty() {
+            args.insert(0, Expression::Cons(product));
+        }
+        if args.len() == 1 {
+            return args.pop().unwrap();
+        }
         return Self::Exp{ f: Function::Product, arguments: args };
     }
 
-    fn simplify_cos(args: &Vec<Expression<T>>) -> Expression<T> {
-        let arg1: Option<&Expression<T>> = args.get(0);
-        match arg1 {
-            (Some(Expression::Cons(a))) => {
-                if *a == T::zero() {
-                    Expression::Cons(T::neutral())
-                }
-                else {
-                    Expression::Exp { f: Function::Cos, arguments: args.clone() }
-                }
+    /// `a / a` gives 1, assuming `a` is not zero where the expression is used: `x / x` is not
+    /// defined at 0 but simplifies to 1.
+    fn simplify_div(args: &Vec<Expression<T>>) -> Expression<T> {
+        let args: Vec<Expression<T>> = args.iter().map(|a| a.simplify()).collect();
+        let zero = Expression::Cons(T::zero());
+        return match args.as_slice() {
+            [_, b] if *b == zero => Expression::Exp { f: Function::Div, arguments: args },
+            [a, _] if *a == zero => zero,
+            [a, b] if a == b => Expression::Cons(T::neutral()),
+            [a, Expression::Cons(b)] => match b.inverse() {
+                Some(inverse) => Self::simplify_mul(&vec![a.clone(), Expression::Cons(inverse)]),
+                None => Expression::Exp { f: Function::Div, arguments: args }
             },
-            _ => Expression::Exp {f: Function::Cos, arguments: args.clone() }
+            _ => Expression::Exp { f: Function::Div, arguments: args }
+        }
+    }
+
+    fn simplify_cos(args: &Vec<Expression<T>>) -> Expression<T> {
+        let args: Vec<Expression<T>> = args.iter().map(|a| a.simplify()).collect();
+        return match args.as_slice() {
+            [Expression::Cons(a)] if *a == T::zero() => Expression::Cons(T::neutral()),
+            _ => Expression::Exp { f: Function::Cos, arguments: args }
+        }
+    }
+
+    fn simplify_sin(args: &Vec<Expression<T>>) -> Expression<T> {
+        let args: Vec<Expression<T>> = args.iter().map(|a| a.simplify()).collect();
+        return match args.as_slice() {
+            [Expression::Cons(a)] if *a == T::zero() => Expression::Cons(T::zero()),
+            _ => Expression::Exp { f: Function::Sin, arguments: args }
         }
     }
 
+    /// Simplified derivative with respect to the variable `var`.
+    /// Functions must have their number of arguments, as for `evaluate`.
+    pub fn derive(&self, var: &str) -> Result<Expression<T>, Error> {
+        return Ok(self.derivative(var)?.simplify());
+    }
+
+    fn derivative(&self, var: &str) -> Result<Expression<T>, Error> {
+        let call = |f: Function, arguments: Vec<Expression<T>>| Expression::Exp { f, arguments };
+        let (f, arguments) = match self {
+            Expression::Cons(_) => return Ok(Expression::Cons(T::zero())),
+            Expression::Var(name) => return Ok(Expression::Cons(if name == var { T::neutral() } else { T::zero() })),
+            Expression::Exp { f, arguments } => (*f, arguments)
+        };
+        if let Some(arity) = f.arity().filter(|n| *n != arguments.len()) {
+            return Err(Error { cause: format!("{:?} takes {} arguments, not {}", f, arity, arguments.len()) });
+        }
+        let derivatives = arguments.iter().map(|a| a.derivative(var)).collect::<Result<Vec<_>, Error>>()?;
+        return Ok(match (f, arguments.as_slice(), derivatives.as_slice()) {
+            (Function::Sum | Function::Sub, _, _) => call(f, derivatives),
+            // (uvw)' = u'vw + uv'w + uvw'
+            (Function::Product, _, _) => call(Function::Sum, derivatives.into_iter().enumerate().map(|(i, d)| {
+                let mut factors = arguments.clone();
+                factors[i] = d;
+                call(Function::Product, factors)
+            }).collect()),
+            // (u/v)' = (u'v - uv') / v²
+            (Function::Div, [u, v], [du, dv]) => call(Function::Div, vec![
+                call(Function::Sub, vec![
+                    call(Function::Product, vec![du.clone(), v.clone()]),
+                    call(Function::Product, vec![u.clone(), dv.clone()]),
+                ]),
+                call(Function::Product, vec![v.clone(), v.clone()]),
+            ]),
+            (Function::Cos, [u], [du]) => call(Function::Product, vec![
+                Expression::Cons(T::neutral().minus()), call(Function::Sin, vec![u.clone()]), du.clone()
+            ]),
+            (Function::Sin, [u], [du]) => call(Function::Product, vec![call(Function::Cos, vec![u.clone()]), du.clone()]),
+            (_, _, _) => unreachable!("arity checked above")
+        });
+    }
+
+    /// `a - b` is `a + (-1) * b`, so that its terms are collected with the others.
     fn simplify_sub(args: &Vec<Expression<T>>) -> Expression<T> {
-        let arg1: Option<Expression<T>> = args.get(0).map(|x| { x.simplify() });
-        let arg2: Option<Expression<T>> = args.get(1).map(|x| { x.simplify() });
-        match (arg1, arg2) {
-            (Some(Expression::Cons(a)), Some(Expression::Cons(b))) => Expression::Cons(a + b.minus()),
-            (Some(e),  Some(Expression::Cons(b))) => Expression::Exp {f: Function::Sum,
-                arguments : vec![e.clone(), Expression::Cons(b.minus())]},
-            (Some(e1), Some(e2)) => {
-                if e1.eq(&e2) {
-                    Expression::Cons(T::zero())
+        let args: Vec<Expression<T>> = args.iter().map(|a| a.simplify()).collect();
+        return match args.as_slice() {
+            [a, b] if a == b => Expression::Cons(T::zero()),
+            [a, b] => Self::simplify_add(&vec![a.clone(), Expression::Exp {
+                f: Function::Product, arguments: vec![Expression::Cons(T::neutral().minus()), b.clone()]
+            }]),
+            _ => Expression::Exp { f: Function::Sub, arguments: args }
+        }
+    }
+}
+
+impl<T: Ring + Add<Output = T> + Mul<Output = T> + PartialEq + Eq + Ord + Into<f64>> Expression<T> {
+    /// Value of the expression with the variables bound in `env`.
+    pub fn evaluate(&self, env: &Environment) -> Result<f64, Error> {
+        return match self {
+            Expression::Cons(x) => Ok((*x).into()),
+            Expression::Var(name) => env.get(name).ok_or_else(|| Error { cause: format!("Unbound variable {}", name) }),
+            Expression::Exp { f, arguments } => {
+                if let Some(arity) = f.arity().filter(|n| *n != arguments.len()) {
+                    return Err(Error { cause: format!("{:?} takes {} arguments, not {}", f, arity, arguments.len()) });
                 }
-                else {
-                    Expression::Exp {f: Function::Sub, arguments: args.clone() }
+                let values = arguments.iter().map(|a| a.evaluate(env)).collect::<Result<Vec<f64>, Error>>()?;
+                if *f == Function::Div && values[1] == 0.0 {
+                    return Err(Error { cause: "Division by zero".to_string() });
                 }
-            },
-            (_, _) => Expression::Exp {f: Function::Sub, arguments: args.clone() }
+                Ok(f.calc(values))
+            }
         }
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -466,8 +940,12 @@ mod tests {
             return StrTest { content: 1 };
         }
 
-        fn inverse(&self) -> Self {
-            StrTest { content : 1/self.content }
+        fn inverse(&self) -> Option<Self> {
+            if self.content == 1 || self.content == -1 {
+                Some(StrTest { content : self.content })
+            } else {
+                None
+            }
         }
     }
 
@@ -481,6 +959,106 @@ mod tests {
         }
     }
 
+    impl MatrixElement<StrTest> for StrTest {
+        fn mult(&self, x: StrTest) -> StrTest {
+            return *self * x;
+        }
+
+        fn multiply(&self, r: Self) -> Self {
+            return *self * r;
+        }
+    }
+
+    fn matrix<const N: usize>(lines: [[f64; N]; N]) -> Matrix<f64, f64, N> {
+        return Matrix::new(lines.map(|values| Vector { values }));
+    }
+
+    fn assert_near<const N: usize>(expected: [[f64; N]; N], m: Matrix<f64, f64, N>) {
+        for line in 0..N {
+            for col in 0..N {
+                assert!((expected[line][col] - m.elements[line].values[col]).abs() < 1e-9,
+                        "{:?} != {:?}", expected, m);
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_identity() {
+        let id: Matrix<f64, f64, 3> = Matrix::neutral();
+        assert_near([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], id.inverse().unwrap());
+        assert_eq!(Some(1.0), id.determinant());
+    }
+
+    #[test]
+    fn inverse_2x2() {
+        let m = matrix([[4.0, 7.0], [2.0, 6.0]]);
+        assert!((m.determinant().unwrap() - 10.0).abs() < 1e-9);
+        let inv = m.inverse().unwrap();
+        assert_near([[0.6, -0.7], [-0.2, 0.4]], inv);
+        assert_near([[1.0, 0.0], [0.0, 1.0]], m * inv);
+
+        // Over integers only units can be pivots.
+        let s = |content| StrTest { content };
+        let unimodular: Matrix<StrTest, StrTest, 2> = Matrix::new([
+            Vector { values: [s(2), s(1)] },
+            Vector { values: [s(1), s(1)] }
+        ]);
+        let inv = unimodular.inverse().unwrap();
+        assert_eq!([s(1), s(-1)], inv.elements[0].values);
+        assert_eq!([s(-1), s(2)], inv.elements[1].values);
+        assert_eq!(Some(s(1)), unimodular.determinant());
+        // Without a unit pivot the integer determinant is unknown, not zero.
+        let diagonal = |a, b| -> Matrix<StrTest, StrTest, 2> {
+            Matrix::new([Vector { values: [s(a), s(0)] }, Vector { values: [s(0), s(b)] }])
+        };
+        assert_eq!(None, diagonal(2, 2).determinant());
+        assert_eq!(Some(s(0)), diagonal(1, 0).determinant());
+        assert!(diagonal(2, 2).inverse().is_none());
+    }
+
+    #[test]
+    fn inverse_3x3() {
+        let m = matrix([[0.0, 2.0, 1.0], [1.0, 0.0, 0.0], [3.0, 0.0, 1.0]]);
+        assert!((m.determinant().unwrap() + 2.0).abs() < 1e-9);
+        let inv = m.inverse().unwrap();
+        assert_near([[0.0, 1.0, 0.0], [0.5, 1.5, -0.5], [0.0, -3.0, 1.0]], inv);
+        assert_near([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], m * inv);
+        assert_near([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], inv * m);
+    }
+
+    #[test]
+    fn inverse_singular() {
+        let m = matrix([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]);
+        assert!(m.inverse().is_none());
+        assert_eq!(Some(0.0), m.determinant());
+        assert!(matrix([[1.0, 2.0], [2.0, 4.0]]).inverse().is_none());
+        // Rounding noise at a big scale is still singular.
+        assert!(matrix([[1e6, 2e6], [2e6, 4e6 + 1e-7]]).inverse().is_none());
+        assert!(matrix([[0.0, 0.0], [0.0, 0.0]]).inverse().is_none());
+    }
+
+    #[test]
+    fn inverse_mixed_scales() {
+        // Small elements are only negligible next to the terms they are computed from.
+        let diagonal = matrix([[1e12, 0.0], [0.0, 1.0]]);
+        assert_eq!(Some(1e12), diagonal.determinant());
+        assert_near([[1e-12, 0.0], [0.0, 1.0]], diagonal.inverse().unwrap());
+        let translation: Matrix<f64, f64, 4> = Matrix::translation([1e11, 0.0, 0.0]);
+        assert_eq!(Some(1.0), translation.determinant());
+        let inverse = translation.inverse().unwrap();
+        assert_eq!(-1e11, inverse.elements[0].values[3]);
+        assert_near([[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]], translation * inverse);
+    }
+
+    #[test]
+    fn inverse_small_scale() {
+        let m = matrix([[4e-6, 7e-6], [2e-6, 6e-6]]);
+        assert!((m.determinant().unwrap() - 1e-11).abs() < 1e-20);
+        assert_near([[1.0, 0.0], [0.0, 1.0]], m * m.inverse().unwrap());
+        let tiny = matrix([[1e-12, 0.0], [0.0, 2e-12]]);
+        assert_near([[1.0, 0.0], [0.0, 0.5]], tiny.inverse().unwrap() * matrix([[1e-12, 0.0], [0.0, 1e-12]]));
+    }
+
     #[test]
     fn add() {
         let x1 : Vector<StrTest, 2> = Vector {
@@ -555,4 +1133,101 @@ mod tests {
             _ => panic!("not ok")
         }
     }
+
+    fn sum(arguments: Vec<Expression<i32>>) -> Expression<i32> {
+        return Expression::Exp { f: Function::Sum, arguments };
+    }
+
+    fn product(arguments: Vec<Expression<i32>>) -> Expression<i32> {
+        return Expression::Exp { f: Function::Product, arguments };
+    }
+
+    fn sub(a: Expression<i32>, b: Expression<i32>) -> Expression<i32> {
+        return Expression::Exp { f: Function::Sub, arguments: vec![a, b] };
+    }
+
+    #[test]
+    fn variables() {
+        let x: Expression<i32> = Expression::var("x");
+        let y: Expression<i32> = Expression::var("y");
+        let c = Expression::Cons;
+        assert_eq!(c(0), sub(x.clone(), x.clone()).simplify());
+        assert_eq!(c(0), product(vec![x.clone(), c(0), y.clone()]).simplify());
+        assert_eq!(y, sub(sum(vec![x.clone(), y.clone()]), x.clone()).simplify());
+        // x + 1 + x + 2
+        assert_eq!(sum(vec![c(3), product(vec![c(2), x.clone()])]),
+                   sum(vec![x.clone(), c(1), x.clone(), c(2)]).simplify());
+        // 3xy - yx - 2x*y, with the last terms nested
+        let xy = product(vec![x.clone(), y.clone()]);
+        assert_eq!(c(0), sum(vec![product(vec![c(3), x.clone(), y.clone()]),
+                                  sub(product(vec![c(-1), y.clone(), x.clone()]), product(vec![c(2), xy.clone()]))]).simplify());
+        assert_eq!(xy, sub(product(vec![c(2), x.clone(), y.clone()]), product(vec![y.clone(), x.clone()])).simplify());
+        assert_eq!(x, Expression::Exp { f: Function::Div, arguments: vec![x.clone(), c(1)] }.simplify());
+        assert_eq!(c(1), Expression::Exp { f: Function::Div, arguments: vec![x.clone(), x.clone()] }.simplify());
+        assert_eq!(c(0), Expression::Exp { f: Function::Sin, arguments: vec![sub(y.clone(), y.clone())] }.simplify());
+    }
+
+    #[test]
+    fn overflow() {
+        let parse = |text: &str| -> Expression<i32> { crate::parser::parse_expression(text).unwrap() };
+        let env = Environment::default().bind("x", 3.0);
+        for text in ["2147483647 + 1", "2147483647 * x + 2147483647 * x", "65536 * 65536 * x", "-2147483647 - 2"] {
+            let simplified = parse(text).simplify();
+            assert_eq!(parse(text).evaluate(&env).unwrap(), simplified.evaluate(&env).unwrap(), "{}", text);
+            assert_eq!(simplified, simplified.simplify(), "{}", text);
+        }
+        assert_eq!(Expression::Cons(i32::MAX), parse("2147483646 + 1").simplify());
+    }
+
+    #[test]
+    fn evaluate() {
+        let x: Expression<i32> = Expression::var("x");
+        let t: Expression<i32> = Expression::var("t");
+        let env = Environment::default().bind("x", 2.0).bind("t", 0.5);
+        let e = sum(vec![
+            product(vec![Expression::Cons(3), x.clone()]),
+            Expression::Exp { f: Function::Cos, arguments: vec![t.clone()] },
+            Expression::Exp { f: Function::Div, arguments: vec![x.clone(), Expression::Cons(4)] },
+        ]);
+        assert!((e.evaluate(&env).unwrap() - (6.0 + 0.5f64.cos() + 0.5)).abs() < 1e-12);
+        assert!((e.evaluate(&env).unwrap() - e.simplify().evaluate(&env).unwrap()).abs() < 1e-12);
+
+        assert_eq!("Unbound variable y", Expression::<i32>::var("y").evaluate(&env).unwrap_err().cause);
+        let by_zero = Expression::Exp { f: Function::Div, arguments: vec![t.clone(), sub(x.clone(), x.clone())] };
+        assert_eq!("Division by zero", by_zero.evaluate(&env).unwrap_err().cause);
+        let two_args = Expression::Exp { f: Function::Sin, arguments: vec![t.clone(), x.clone()] };
+        assert_eq!("Sin takes 1 arguments, not 2", two_args.evaluate(&env).unwrap_err().cause);
+    }
+
+    #[test]
+    fn derive() {
+        let parse = |text: &str| -> Expression<i32> { crate::parser::parse_expression(text).unwrap() };
+        assert_eq!(parse("2 * x"), parse("x * x").derive("x").unwrap());
+        assert_eq!(Expression::Cons(3), parse("3*x + y").derive("x").unwrap());
+        assert_eq!(Expression::Cons(0), parse("cos(y) / 2").derive("x").unwrap());
+        assert_eq!(parse("-1 * sin(x)"), parse("cos(x)").derive("x").unwrap());
+        assert_eq!(parse("2 * cos(2 * x)"), parse("sin(2*x)").derive("x").unwrap());
+
+        let three = Expression::Exp { f: Function::Sub, arguments: vec![parse("x"), parse("x"), parse("x")] };
+        assert_eq!("Sub takes 2 arguments, not 3", three.derive("x").unwrap_err().cause);
+        let nested: Expression<i32> = Expression::Exp { f: Function::Cos, arguments: vec![Expression::Exp { f: Function::Sin, arguments: vec![] }] };
+        assert_eq!("Sin takes 1 arguments, not 0", nested.derive("x").unwrap_err().cause);
+    }
+
+    #[test]
+    fn derive_finite_differences() {
+        let h = 1e-5;
+        for text in ["2*cos(t) + sin(t)/3 - t", "t*t*t - 4*t", "sin(t)/cos(t)", "cos(2*t*x) - x", "x/(t + 3)",
+                     "cos(t)*cos(t)", "sin(sin(t)) * -t", "1/(1 + t*t)", "(t - x)*(t + x)/(2 - sin(t))"] {
+            let e: Expression<i32> = crate::parser::parse_expression(text).unwrap();
+            let derivative = e.derive("t").unwrap();
+            for t in [-1.3, 0.2, 0.7, 2.1] {
+                let at = |t: f64| Environment::default().bind("t", t).bind("x", 0.6);
+                let expected = (e.evaluate(&at(t + h)).unwrap() - e.evaluate(&at(t - h)).unwrap()) / (2.0 * h);
+                let actual = derivative.evaluate(&at(t)).unwrap();
+                assert!((expected - actual).abs() < 1e-6 * expected.abs().max(1.0),
+                        "d({})/dt = {} is {} at t = {}, not {}", text, derivative, actual, t, expected);
+            }
+        }
+    }
 }
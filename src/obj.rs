@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use crate::points::{EdgeAdder, EdgeStyle, Error, FaceAdder, Figure, LoadError, PointAdder, Scalar};
+
+/// How non integer vertex coordinates are turned into `Point` coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// Keep the fractional part, only possible for floating point figures.
+    None,
+    Nearest,
+    Floor,
+    Ceil,
+    Truncate,
+}
+
+/// Coordinates are multiplied by `scale` and then rounded.
+#[derive(Debug, Clone, Copy)]
+pub struct ScalePolicy {
+    pub scale: f64,
+    pub rounding: Rounding,
+}
+
+impl Default for ScalePolicy {
+    fn default() -> Self {
+        return ScalePolicy { scale: 1.0, rounding: Rounding::None };
+    }
+}
+
+impl ScalePolicy {
+    pub fn apply(&self, v: f64) -> f64 {
+        let scaled = v * self.scale;
+        return match self.rounding {
+            Rounding::None => scaled,
+            Rounding::Nearest => scaled.round(),
+            Rounding::Floor => scaled.floor(),
+            Rounding::Ceil => scaled.ceil(),
+            Rounding::Truncate => scaled.trunc(),
+        };
+    }
+}
+
+/// Reads a Wavefront OBJ source: `v` lines become points, `f` and `l` elements become edges
+/// and `f` elements faces too. Edges shared by several faces are only added once.
+pub fn loadObj<T: Scalar>(source: &str, policy: &ScalePolicy) -> Result<Figure<3, T>, LoadError> {
+    fn error(line: usize, cause: String) -> LoadError {
+        return LoadError::Obj { line, error: Error { cause } };
+    }
+    fn vertex<T: Scalar>(line: usize, args: &[&str], policy: &ScalePolicy) -> Result<[T; 3], LoadError> {
+        if args.len() < 3 {
+            return Err(error(line, format!("vertex has {} coordinates, 3 expected", args.len())));
+        }
+        let mut result: [T; 3] = [T::from_i64(0); 3];
+        for i in 0..3 {
+            let value = match args[i].parse::<f64>() {
+                Ok(v) if v.is_finite() => policy.apply(v),
+                _ => return Err(error(line, format!("invalid coordinate '{}'", args[i])))
+            };
+            result[i] = match T::from_f64(value) {
+                Some(v) => v,
+                None => return Err(error(line, format!("coordinate {} is not an integer, choose a rounding", value)))
+            };
+        }
+        return Ok(result);
+    }
+    fn index(line: usize, arg: &str, nb_points: usize) -> Result<usize, LoadError> {
+        // "v", "v/vt", "v//vn" or "v/vt/vn": only the vertex part matters.
+        let v = arg.split('/').next().unwrap_or("");
+        let i = match v.parse::<i64>() {
+            Ok(i) => i,
+            Err(_) => return Err(error(line, format!("invalid vertex reference '{}'", arg)))
+        };
+        let resolved = if i > 0 {
+            i - 1
+        } else {
+            nb_points as i64 + i
+        };
+        if i == 0 || resolved < 0 || resolved >= nb_points as i64 {
+            return Err(error(line, format!("vertex {} is not defined", i)));
+        }
+        return Ok(resolved as usize);
+    }
+
+    let mut figure: Figure<3, T> = Figure::new();
+    let mut known: HashSet<(usize, usize)> = HashSet::new();
+    let mut add = |figure: &mut Figure<3, T>, a: usize, b: usize| {
+        if a != b && known.insert((a.min(b), a.max(b))) {
+            figure.add_edge(a, b);
+        }
+    };
+    for (number, content) in source.lines().enumerate() {
+        let line = number + 1;
+        let data = content.split('#').next().unwrap_or("");
+        let mut tokens = data.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue
+        };
+        let args: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => {
+                let p = vertex(line, &args, policy)?;
+                figure.add_point(p);
+            },
+            "f" | "l" => {
+                let min = if keyword == "f" { 3 } else { 2 };
+                if args.len() < min {
+                    return Err(error(line, format!("'{}' element needs at least {} vertices", keyword, min)));
+                }
+                let mut indexes: Vec<usize> = vec![];
+                for a in args.iter() {
+                    indexes.push(index(line, a, figure.points.len())?);
+                }
+                for i in 0..indexes.len() - 1 {
+                    add(&mut figure, indexes[i], indexes[i + 1]);
+                }
+                if keyword == "f" {
+                    add(&mut figure, indexes[indexes.len() - 1], indexes[0]);
+                    figure.add_face(&indexes);
+                }
+            },
+            _ => {} // normals, textures, groups and materials are not used.
+        }
+    }
+    return Ok(figure);
+}
+
+/// Writes points as `v` lines, edges as `l` elements and faces as `f` elements.
+/// OBJ can't hold everything a figure has, `obj_losses` tells what reading it back changes.
+pub fn saveObj<T: Scalar>(figure: &Figure<3, T>) -> String {
+    let mut result = String::new();
+    for p in figure.points.iter() {
+        result.push_str(&format!("v {} {} {}\n", p.coords[0], p.coords[1], p.coords[2]));
+    }
+    // Edges first so that reading the file back keeps their order.
+    for e in figure.edges.iter() {
+        result.push_str(&format!("l {} {}\n", e.points.0 + 1, e.points.1 + 1));
+    }
+    for f in figure.faces.iter() {
+        let indexes: Vec<String> = f.points.iter().map(|i| (i + 1).to_string()).collect();
+        result.push_str(&format!("f {}\n", indexes.join(" ")));
+    }
+    return result;
+}
+
+/// What reading back the output of `saveObj` changes in the figure, empty when nothing.
+pub fn obj_losses<T: Scalar>(figure: &Figure<3, T>) -> Vec<String> {
+    let key = |a: usize, b: usize| (a.min(b), a.max(b));
+    let mut edges: HashSet<(usize, usize)> = HashSet::new();
+    let (mut loops, mut duplicates) = (0, 0);
+    for e in figure.edges.iter() {
+        let (a, b) = e.points;
+        if a == b {
+            loops += 1;
+        } else if !edges.insert(key(a, b)) {
+            duplicates += 1;
+        }
+    }
+    let sides: HashSet<(usize, usize)> = figure.faces.iter()
+        .flat_map(|f| (0..f.points.len()).map(move |i| key(f.points[i], f.points[(i + 1) % f.points.len()])))
+        .filter(|(a, b)| a != b && !edges.contains(&(*a, *b)))
+        .collect();
+    let mut result: Vec<String> = vec![];
+    if loops > 0 {
+        result.push(format!("{} edges from a point to itself are left out", loops));
+    }
+    if duplicates > 0 {
+        result.push(format!("{} duplicate edges are merged", duplicates));
+    }
+    if !sides.is_empty() {
+        result.push(format!("{} face sides that are not edges become edges", sides.len()));
+    }
+    if figure.edges.iter().any(|e| e.style != EdgeStyle::default()) {
+        result.push("edge styles are not saved".to_string());
+    }
+    if !figure.point_styles.is_empty() {
+        result.push("point labels and markers are not saved".to_string());
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE: &str = "
+# unit cube
+o cube
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+vn 0 0 1
+f 1 2 3 4
+f 5/1 6/2 7/3 8/4
+f 1//1 2//1 6//1 5//1
+f 2 3 7 6
+f -5 -6 -2 -1
+f 4 1 5 8
+";
+
+    #[test]
+    fn cube() {
+        let fig: Figure<3, i64> = loadObj(CUBE, &ScalePolicy { scale: 100.0, rounding: Rounding::Nearest }).unwrap();
+        assert_eq!(8, fig.points.len());
+        assert_eq!(12, fig.edges.len());
+        assert_eq!(6, fig.faces.len());
+        assert_eq!(vec![4, 5, 6, 7], fig.faces[1].points);
+        assert_eq!([100, 100, 100], fig.points[6].coords);
+        assert!(fig.validate().is_empty());
+    }
+
+    #[test]
+    fn save() {
+        let fig: Figure<3> = loadObj(CUBE, &ScalePolicy::default()).unwrap();
+        let saved = saveObj(&fig);
+        assert!(saved.starts_with("v 0 0 0\nv 1 0 0\n"));
+        let reloaded: Figure<3> = loadObj(&saved, &ScalePolicy::default()).unwrap();
+        assert_eq!(fig.points, reloaded.points);
+        assert_eq!(fig.edges, reloaded.edges);
+        assert_eq!(fig.faces, reloaded.faces);
+        assert!(obj_losses(&fig).is_empty());
+    }
+
+    #[test]
+    fn lossy_save() {
+        // A triangle with a single edge along its sides, a loop and a styled edge.
+        let mut fig: Figure<3> = Figure::new();
+        for p in [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            fig.add_point(p);
+        }
+        fig.add_edge(0, 1);
+        fig.add_edge(2, 2);
+        fig.add_face(&[0, 1, 2]);
+        fig.edges[0].style.width = Some(3.0);
+        assert_eq!(vec!["1 edges from a point to itself are left out", "2 face sides that are not edges become edges",
+                        "edge styles are not saved"], obj_losses(&fig));
+        let reloaded: Figure<3> = loadObj(&saveObj(&fig), &ScalePolicy::default()).unwrap();
+        assert_eq!(fig.points, reloaded.points);
+        assert_eq!(fig.faces, reloaded.faces);
+        let ends: Vec<(usize, usize)> = reloaded.edges.iter().map(|e| e.points).collect();
+        assert_eq!(vec![(0, 1), (1, 2), (2, 0)], ends);
+        assert!(obj_losses(&reloaded).is_empty());
+    }
+
+    #[test]
+    fn rounding() {
+        let source = "v 0.25 -0.25 1.5\nl 1 1\n";
+        let policy = |rounding| ScalePolicy { scale: 10.0, rounding };
+        assert_eq!([3, -3, 15], loadObj::<i64>(source, &policy(Rounding::Nearest)).unwrap().points[0].coords);
+        assert_eq!([2, -3, 15], loadObj::<i64>(source, &policy(Rounding::Floor)).unwrap().points[0].coords);
+        assert_eq!([3, -2, 15], loadObj::<i64>(source, &policy(Rounding::Ceil)).unwrap().points[0].coords);
+        assert_eq!([2, -2, 15], loadObj::<i64>(source, &policy(Rounding::Truncate)).unwrap().points[0].coords);
+        assert_eq!([2.5, -2.5, 15.0], loadObj::<f64>(source, &policy(Rounding::None)).unwrap().points[0].coords);
+        assert_eq!(0, loadObj::<f64>(source, &policy(Rounding::Nearest)).unwrap().edges.len());
+        assert!(loadObj::<i64>(source, &policy(Rounding::None)).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        match loadObj::<f64>("v 0 0 0\nv 1 1 1\nl 1 3\n", &ScalePolicy::default()) {
+            Err(LoadError::Obj { line, .. }) => assert_eq!(3, line),
+            _ => panic!("undefined vertex error expected")
+        }
+        match loadObj::<f64>("v 0 zero 0\n", &ScalePolicy::default()) {
+            Err(LoadError::Obj { line, .. }) => assert_eq!(1, line),
+            _ => panic!("coordinate error expected")
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Infix text to `matrix::Expression`, the reverse of its `Display`.
+//!
+//! ```text
+//! sum     := product (('+' | '-') product)*
+//! product := unary (('*' | '/') unary)*
+//! unary   := '-' unary | number | name | name '(' sum (',' sum)* ')' | '(' sum ')'
+//! ```
+//! Repeated `+` and `*` give a single sum or product, `-` and `/` are left associative.
+//! A `-` before a number is part of the constant, before anything else it multiplies by -1.
+
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+use crate::matrix::{Expression, Function, Ring};
+use crate::points::Error;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    /// Column of the text where the error is, from 1.
+    pub column: usize,
+    pub cause: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.cause)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        return Error { cause: e.to_string() };
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    /// Next character that is not a space, None at the end.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.at).is_some_and(|c| c.is_whitespace()) {
+            self.at += 1;
+        }
+        return self.chars.get(self.at).copied();
+    }
+
+    fn error(&self, at: usize, cause: String) -> ParseError {
+        return ParseError { column: at + 1, cause };
+    }
+
+    fn unexpected(&mut self) -> ParseError {
+        return match self.peek() {
+            Some(c) => self.error(self.at, format!("unexpected '{}'", c)),
+            None => self.error(self.at, "unexpected end of expression".to_string())
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.peek() != Some(expected) {
+            let found = self.unexpected();
+            return Err(ParseError { cause: format!("{}, expected '{}'", found.cause, expected), ..found });
+        }
+        self.at += 1;
+        return Ok(());
+    }
+
+    /// Characters from the current position while `accept` holds.
+    fn take(&mut self, accept: fn(char) -> bool) -> String {
+        let start = self.at;
+        while self.chars.get(self.at).is_some_and(|c| accept(*c)) {
+            self.at += 1;
+        }
+        return self.chars[start..self.at].iter().collect();
+    }
+
+    /// Operands of operators of the same precedence: repeated `gather` operators make a single expression.
+    fn operation<T, F>(&mut self, gather: (char, Function), other: (char, Function), operand: F) -> Result<Expression<T>, ParseError>
+        where T: Ring + Add<Output = T> + Mul<Output = T> + Ord + FromStr,
+              F: Fn(&mut Parser) -> Result<Expression<T>, ParseError> {
+        let mut left = operand(self)?;
+        let mut gathering = false;
+        loop {
+            let c = self.peek();
+            if c == Some(gather.0) {
+                self.at += 1;
+                let right = operand(self)?;
+                match &mut left {
+                    Expression::Exp { arguments, .. } if gathering => arguments.push(right),
+                    _ => left = Expression::Exp { f: gather.1, arguments: vec![left, right] }
+                }
+                gathering = true;
+            } else if c == Some(other.0) {
+                self.at += 1;
+                let right = operand(self)?;
+                left = Expression::Exp { f: other.1, arguments: vec![left, right] };
+                gathering = false;
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn sum<T>(&mut self) -> Result<Expression<T>, ParseError>
+        where T: Ring + Add<Output = T> + Mul<Output = T> + Ord + FromStr {
+        return self.operation(('+', Function::Sum), ('-', Function::Sub), |p| p.product());
+    }
+
+    fn product<T>(&mut self) -> Result<Expression<T>, ParseError>
+        where T: Ring + Add<Output = T> + Mul<Output = T> + Ord + FromStr {
+        return self.operation(('*', Function::Product), ('/', Function::Div), |p| p.unary());
+    }
+
+    /// Digits read with the `sign` just before them, so that the most negative value parses too.
+    fn number<T: FromStr>(&mut self, sign: &str) -> Result<T, ParseError> {
+        let start = self.at - sign.len();
+        let text = format!("{}{}", sign, self.take(|c| c.is_ascii_digit()));
+        return text.parse().map_err(|_| self.error(start, format!("bad number {}", text)));
+    }
+
+    fn unary<T>(&mut self) -> Result<Expression<T>, ParseError>
+        where T: Ring + Add<Output = T> + Mul<Output = T> + Ord + FromStr {
+        let next = self.peek();
+        let start = self.at;
+        match next {
+            Some('-') => {
+                self.at += 1;
+                if self.chars.get(self.at).is_some_and(|c| c.is_ascii_digit()) {
+                    return Ok(Expression::Cons(self.number("-")?));
+                }
+                let operand = self.unary()?;
+                return Ok(Expression::Exp { f: Function::Product, arguments: vec![Expression::Cons(T::neutral().minus()), operand] });
+            },
+            Some('(') => {
+                self.at += 1;
+                let inner = self.sum()?;
+                self.expect(')')?;
+                return Ok(inner);
+            },
+            Some(c) if c.is_ascii_digit() => return Ok(Expression::Cons(self.number("")?)),
+            Some(c) if c.is_alphabetic() || c == '_' => {},
+            _ => return Err(self.unexpected())
+        }
+        let name = self.take(|c| c.is_alphanumeric() || c == '_');
+        if self.peek() != Some('(') {
+            return Ok(Expression::Var(name));
+        }
+        let f = Function::from_name(&name).ok_or_else(|| self.error(start, format!("unknown function {}", name)))?;
+        self.at += 1;
+        let mut arguments: Vec<Expression<T>> = vec![];
+        if self.peek() == Some(')') {
+            self.at += 1;
+        } else {
+            loop {
+                arguments.push(self.sum()?);
+                if self.peek() == Some(',') {
+                    self.at += 1;
+                } else {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+        }
+        if let Some(arity) = f.arity().filter(|n| *n != arguments.len()) {
+            return Err(self.error(start, format!("{} takes {} arguments, not {}", name, arity, arguments.len())));
+        }
+        return Ok(Expression::Exp { f, arguments });
+    }
+}
+
+/// Reads an expression such as `2*cos(t) + sin(t)/3 - x`, with integer constants read as `T`.
+pub fn parse_expression<T>(text: &str) -> Result<Expression<T>, ParseError>
+    where T: Ring + Add<Output = T> + Mul<Output = T> + Ord + FromStr {
+    let mut parser = Parser { chars: text.chars().collect(), at: 0 };
+    let expression = parser.sum()?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+    return Ok(expression);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Expression<i32> {
+        return parse_expression(text).unwrap();
+    }
+
+    fn error(text: &str) -> String {
+        return parse_expression::<i32>(text).unwrap_err().to_string();
+    }
+
+    #[test]
+    fn precedence() {
+        let t: Expression<i32> = Expression::var("t");
+        let call = |f, argument: &Expression<i32>| Expression::Exp { f, arguments: vec![argument.clone()] };
+        let expected = Expression::Exp { f: Function::Sub, arguments: vec![
+            Expression::Exp { f: Function::Sum, arguments: vec![
+                Expression::Exp { f: Function::Product, arguments: vec![Expression::Cons(2), call(Function::Cos, &t)] },
+                Expression::Exp { f: Function::Div, arguments: vec![call(Function::Sin, &t), Expression::Cons(3)] },
+            ]},
+            Expression::var("x"),
+        ]};
+        assert_eq!(expected, parse("2*cos(t) + sin(t)/3 - x"));
+        assert_eq!("2 * cos(t) + sin(t) / 3 - x", expected.to_string());
+
+        assert_eq!(parse("sum(a, b, c)"), parse("a + b + c"));
+        assert_eq!(parse("sum(sum(a, b), c)"), parse("(a + b) + c"));
+        assert_eq!(parse("sub(sub(a, b), c)"), parse("a - b - c"));
+        assert_eq!(parse("sum(sub(a, b), c)"), parse("a-b+c"));
+        assert_eq!(parse("div(product(a, b), c)"), parse("a * b / c"));
+        assert_eq!(parse("product(-1, x)"), parse("-x"));
+        assert_eq!(Expression::Cons(-3), parse(" -3 "));
+        assert_eq!(Expression::Cons(i32::MIN), parse("-2147483648"));
+        assert_eq!(parse("product(2, -1, product(-1, product(-1, x)))"), parse("2 * -1 * --x"));
+        assert_eq!(7, parse("(((7)))").evaluate(&Default::default()).unwrap() as i32);
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in ["2 * cos(t) + sin(t) / 3 - x", "(a + b) + c", "a + (b + c)", "a - (b - c)", "a - b + c",
+                     "(a + b) * (c - d)", "a / (b * c)", "a * b / c", "-1 * x", "x - -3", "sin(-2 * (x + y))",
+                     "sum()", "product(x)", "cos(t_0) * cos(t_1)", "sum(a, b) / 2"] {
+            let e = parse(text);
+            let printed = e.to_string();
+            assert_eq!(e, parse(&printed), "{} printed as {}", text, printed);
+        }
+        assert_eq!("(a + b) / 2", parse("sum(a, b) / 2").to_string());
+        let simplified = parse("x - 2*y + x - cos(0)").simplify();
+        assert_eq!("-1 + -2 * y + 2 * x", simplified.to_string());
+        assert_eq!(simplified, parse(&simplified.to_string()));
+        assert_eq!("a - (b - c)", parse("a-(b-c)").to_string());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("column 1: unexpected end of expression", error(""));
+        assert_eq!("column 5: unexpected end of expression", error("2 * "));
+        assert_eq!("column 4: unexpected ')'", error("x +)"));
+        assert_eq!("column 7: unexpected end of expression, expected ')'", error("cos(t "));
+        assert_eq!("column 3: unexpected 'y'", error("x y"));
+        assert_eq!("column 3: unknown function tan", error("2+tan(x)"));
+        assert_eq!("column 1: sin takes 1 arguments, not 2", error("sin(x, y)"));
+        assert_eq!("column 5: bad number 99999999999", error("1 + 99999999999"));
+        assert_eq!("column 5: bad number 2147483648", error("1 + 2147483648"));
+        assert_eq!("column 5: bad number -2147483649", error("1 + -2147483649"));
+        assert_eq!("column 2: unexpected '#'", error("(#)"));
+        let e: Error = parse_expression::<i32>("x +").unwrap_err().into();
+        assert_eq!("column 4: unexpected end of expression", e.cause);
+    }
+}
@@ -1,153 +1,699 @@
+use eframe::epaint::Color32;
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// Coordinate type of points: integers for the historic .fig files, floats for everything
+/// that needs sub unit precision.
+pub trait Scalar : Copy + PartialEq + fmt::Debug + fmt::Display
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> {
+    fn from_i64(v: i64) -> Self;
+
+    /// None if the value can't be represented exactly.
+    fn from_f64(v: f64) -> Option<Self>;
+
+    fn to_f64(&self) -> f64;
+}
+
+impl Scalar for i64 {
+    fn from_i64(v: i64) -> Self {
+        return v;
+    }
+
+    fn from_f64(v: f64) -> Option<Self> {
+        if v.fract() == 0.0 && v >= i64::MIN as f64 && v <= i64::MAX as f64 {
+            Some(v as i64)
+        } else {
+            None
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        return *self as f64;
+    }
+}
+
+impl Scalar for f64 {
+    fn from_i64(v: i64) -> Self {
+        return v as f64;
+    }
+
+    fn from_f64(v: f64) -> Option<Self> {
+        if v.is_finite() { Some(v) } else { None }
+    }
+
+    fn to_f64(&self) -> f64 {
+        return *self;
+    }
+}
 
 //#[derive(Clone, Copy)]
-pub struct Point<const N: usize> {
-    pub coords: [i64; N],
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Point<const N: usize, T = f64> {
+    pub coords: [T; N],
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Edge {
     pub points: (usize, usize),
+    pub style: EdgeStyle,
+}
+
+/// Line pattern of an edge.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Dash {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl Dash {
+    pub const ALL: [Dash; 3] = [Dash::Solid, Dash::Dashed, Dash::Dotted];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dash::Solid => "solid",
+            Dash::Dashed => "dashed",
+            Dash::Dotted => "dotted",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Dash> {
+        return Dash::ALL.iter().find(|d| d.name() == name).copied();
+    }
 }
 
-pub struct Figure<const N: usize> {
-    pub points: Vec<Point<N>>, // [&'a Point<N>; NP],
+/// How an edge is drawn, unset attributes use the screen defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EdgeStyle {
+    /// Unmultiplied RGBA, as written in files.
+    pub color: Option<[u8; 4]>,
+    /// Width in pixels.
+    pub width: Option<f32>,
+    pub dash: Dash,
+}
+
+impl EdgeStyle {
+    /// Premultiplied color to draw the edge with, white when unset.
+    pub fn color32(&self) -> Color32 {
+        return match self.color {
+            Some([r, g, b, a]) => Color32::from_rgba_unmultiplied(r, g, b, a),
+            None => Color32::WHITE
+        };
+    }
+}
+
+/// Shape drawn on a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Marker {
+    Circle,
+    Square,
+    Cross,
+}
+
+impl Marker {
+    pub const ALL: [Marker; 3] = [Marker::Circle, Marker::Square, Marker::Cross];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Marker::Circle => "circle",
+            Marker::Square => "square",
+            Marker::Cross => "cross",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Marker> {
+        return Marker::ALL.iter().find(|m| m.name() == name).copied();
+    }
+}
+
+/// Annotation of a point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointStyle {
+    pub label: Option<String>,
+    pub marker: Option<Marker>,
+    /// Marker width in pixels.
+    pub size: f32,
+}
+
+impl Default for PointStyle {
+    fn default() -> Self {
+        return PointStyle { label: None, marker: None, size: 6.0 };
+    }
+}
+
+/// Parses "#rrggbb" or "#rrggbbaa".
+pub fn parse_color(text: &str) -> Option<[u8; 4]> {
+    let hex = text.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok();
+    let alpha = if hex.len() == 8 { channel(3)? } else { 255 };
+    return Some([channel(0)?, channel(1)?, channel(2)?, alpha]);
+}
+
+pub fn color_name(color: [u8; 4]) -> String {
+    let [r, g, b, a] = color;
+    if a == 255 {
+        return format!("#{:02x}{:02x}{:02x}", r, g, b);
+    }
+    return format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a);
+}
+
+/// Polygon given by the indexes of its points, in order around it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Face {
+    pub points: Vec<usize>,
+}
+
+pub struct Figure<const N: usize, T = f64> {
+    pub points: Vec<Point<N, T>>, // [&'a Point<N>; NP],
     pub edges: Vec<Edge>,
+    /// Optional, only used to hide or fill what is behind them.
+    pub faces: Vec<Face>,
+    /// Labels and markers of the points that have some, by point index.
+    pub point_styles: BTreeMap<usize, PointStyle>,
+}
+
+impl <const N: usize, T> Default for Figure<N, T> {
+    fn default() -> Self {
+        return Figure::new();
+    }
 }
 
-impl <const N: usize> Figure<N> {
-    pub fn edge_points(&self, e: &Edge) -> (&Point<N>, &Point<N>) {
+impl <const N: usize, T> Figure<N, T> {
+    /// Figure without points.
+    pub fn new() -> Figure<N, T> {
+        return Figure { points: vec![], edges: vec![], faces: vec![], point_styles: BTreeMap::new() };
+    }
+
+    pub fn edge_points(&self, e: &Edge) -> (&Point<N, T>, &Point<N, T>) {
         return (&self.points[e.points.0],
                 &self.points[e.points.1]);
     }
+
+    pub fn face_points(&self, face: &Face) -> Vec<&Point<N, T>> {
+        return face.points.iter().map(|p| &self.points[*p]).collect();
+    }
+
+    /// Checks edges against the point list; an empty result means the figure is consistent.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues: Vec<Issue> = vec![];
+        let mut referenced: Vec<bool> = vec![false; self.points.len()];
+        let mut seen: HashMap<(usize, usize), usize> = HashMap::new();
+        for (index, e) in self.edges.iter().enumerate() {
+            let (start, end) = e.points;
+            let mut dangling = false;
+            for p in [start, end] {
+                if p < self.points.len() {
+                    referenced[p] = true;
+                } else {
+                    issues.push(Issue::DanglingEdge { edge: index, end: p });
+                    dangling = true;
+                }
+            }
+            if start == end {
+                issues.push(Issue::SelfLoop { edge: index });
+            }
+            if !dangling {
+                let key = (start.min(end), start.max(end));
+                match seen.get(&key) {
+                    Some(first) => issues.push(Issue::DuplicateEdge { edge: index, first: *first }),
+                    None => { seen.insert(key, index); }
+                }
+            }
+        }
+        for (index, face) in self.faces.iter().enumerate() {
+            for p in face.points.iter() {
+                if *p >= self.points.len() {
+                    issues.push(Issue::DanglingFace { face: index, point: *p });
+                }
+            }
+        }
+        referenced.iter()
+            .enumerate()
+            .filter(|(_, r)| !**r)
+            .for_each(|(point, _)| issues.push(Issue::UnreferencedPoint { point }));
+        return issues;
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Issue {
+    DanglingEdge { edge: usize, end: usize },
+    SelfLoop { edge: usize },
+    DuplicateEdge { edge: usize, first: usize },
+    UnreferencedPoint { point: usize },
+    DanglingFace { face: usize, point: usize },
+}
+
+impl Issue {
+    /// Fatal issues make the figure unusable for drawing.
+    pub fn is_fatal(&self) -> bool {
+        return matches!(self, Issue::DanglingEdge { .. } | Issue::DanglingFace { .. });
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::DanglingEdge { edge, end } => write!(f, "edge {} references missing point {}", edge, end),
+            Issue::SelfLoop { edge } => write!(f, "edge {} starts and ends on the same point", edge),
+            Issue::DuplicateEdge { edge, first } => write!(f, "edge {} duplicates edge {}", edge, first),
+            Issue::UnreferencedPoint { point } => write!(f, "point {} is not used by any edge", point),
+            Issue::DanglingFace { face, point } => write!(f, "face {} references missing point {}", face, point),
+        }
+    }
 }
 
+impl<const N: usize, T: Scalar> Figure<N, T> {
+    /// Mean of all points, origin for an empty figure.
+    pub fn centroid(&self) -> Point<N> {
+        let mut coords: [f64; N] = [0.0; N];
+        for p in self.points.iter() {
+            for i in 0..N {
+                coords[i] += p.coords[i].to_f64();
+            }
+        }
+        if !self.points.is_empty() {
+            for c in coords.iter_mut() {
+                *c /= self.points.len() as f64;
+            }
+        }
+        return Point { coords };
+    }
+
+    /// Smallest and biggest value of each coordinate, None for an empty figure.
+    pub fn bounds(&self) -> Option<(Point<N>, Point<N>)> {
+        let first = self.points.first()?;
+        let mut min: [f64; N] = first.coords.map(|c| c.to_f64());
+        let mut max: [f64; N] = min;
+        for p in self.points.iter() {
+            for i in 0..N {
+                min[i] = min[i].min(p.coords[i].to_f64());
+                max[i] = max[i].max(p.coords[i].to_f64());
+            }
+        }
+        return Some((Point { coords: min }, Point { coords: max }));
+    }
+}
 
-pub trait PointAdder<const N: usize> {
-    fn add_point(&mut self, c: [i64; N]) -> &Self;
+/// Figure of any dimension supported by the viewer, known only at run time.
+pub enum AnyFigure {
+    Three(Figure<3>),
+    Four(Figure<4>),
+}
+
+impl AnyFigure {
+    pub fn validate(&self) -> Vec<Issue> {
+        return match self {
+            AnyFigure::Three(f) => f.validate(),
+            AnyFigure::Four(f) => f.validate(),
+        };
+    }
+
+    pub fn dimension(&self) -> usize {
+        return match self {
+            AnyFigure::Three(_) => 3,
+            AnyFigure::Four(_) => 4,
+        };
+    }
+}
+
+pub trait PointAdder<const N: usize, T = f64> {
+    fn add_point(&mut self, c: [T; N]) -> &Self;
 }
 
 pub trait EdgeAdder {
     fn add_edge(&mut self, start: usize, end: usize)  -> &Self;
 }
 
-impl<const N: usize> PointAdder<N> for Figure<N> {
-    fn add_point(&mut self, c: [i64; N]) -> &Self {
-        let p : Point<N> = Point { coords : c };
+pub trait FaceAdder {
+    fn add_face(&mut self, points: &[usize]) -> &Self;
+}
+
+impl<const N: usize, T> PointAdder<N, T> for Figure<N, T> {
+    fn add_point(&mut self, c: [T; N]) -> &Self {
+        let p : Point<N, T> = Point { coords : c };
         self.points.push(p);
         return self;
     }
 }
 
-impl<const N: usize> EdgeAdder for Figure<N> {
+impl<const N: usize, T> EdgeAdder for Figure<N, T> {
     fn add_edge(&mut self, start: usize, end: usize)  -> &Self {
-        let edge = Edge { points: (start, end) };
+        let edge = Edge { points: (start, end), style: EdgeStyle::default() };
         self.edges.push(edge);
         return self;
     }
 }
 
+impl<const N: usize, T> FaceAdder for Figure<N, T> {
+    fn add_face(&mut self, points: &[usize]) -> &Self {
+        self.faces.push(Face { points: points.to_vec() });
+        return self;
+    }
+}
+
+#[derive(Debug)]
 pub struct Error {
     pub cause: String
 }
 
-pub fn loadFigure(json_source: &str, figure:&mut Figure<3>) {
-
-    fn toPoint(points: &Value) -> Result<[i64; 3], Error> { // move Option to Result
-        match points {
-            Value::Array(x) => if x.len() == 3 {
-                let x0: &Value = &x[0];
-                let x1: &Value = &x[1];
-                let x2: &Value = &x[2];
-                match (x0, x1, x2) {
-                    (Value::Number(v1),
-                        Value::Number(v2),
-                        Value::Number(v3)) =>
-                        match (v1.as_i64(),
-                               v2.as_i64(),
-                               v3.as_i64()) {
-                            (Some(i1), Some(i2), Some(i3)) => Result::Ok([i1, i2, i3]),
-                            _ => Result::Err(Error { cause: "Array size error".to_string() })
-                        }
-
-                    (_,_,_) => Result::Err(Error { cause: "Array size error".to_string() })
+#[derive(Debug)]
+pub enum LoadError {
+    Syntax { line: usize, column: usize, error: Error },
+    NotAnObject,
+    MissingKey(String),
+    NotAnArray(String),
+    CoordinateArity { point: usize, expected: usize, found: usize },
+    NotANumber { point: usize, coordinate: usize },
+    NonIntegerCoordinate { point: usize, coordinate: usize },
+    EdgeArity { edge: usize, found: usize },
+    NonIntegerEdgeEnd { edge: usize },
+    EdgeOutOfRange { edge: usize, end: usize, points: usize },
+    FaceArity { face: usize, found: usize },
+    NonIntegerFaceIndex { face: usize },
+    FaceOutOfRange { face: usize, point: usize, points: usize },
+    /// `item` names the point or edge, `expected` describes valid values of its `key`.
+    BadAttribute { item: String, key: String, expected: &'static str },
+    Obj { line: usize, error: Error },
+}
+
+impl LoadError {
+    pub fn error(&self) -> Error {
+        return Error { cause: self.to_string() };
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Syntax { line, column, error } =>
+                write!(f, "JSON syntax error at line {}, column {}: {}", line, column, error.cause),
+            LoadError::NotAnObject => write!(f, "figure should be a JSON object"),
+            LoadError::MissingKey(key) => write!(f, "missing '{}' key", key),
+            LoadError::NotAnArray(what) => write!(f, "{} should be an array", what),
+            LoadError::CoordinateArity { point, expected, found } =>
+                write!(f, "point {} has {} coordinates, {} expected", point, found, expected),
+            LoadError::NotANumber { point, coordinate } =>
+                write!(f, "coordinate {} of point {} is not a number", coordinate, point),
+            LoadError::NonIntegerCoordinate { point, coordinate } =>
+                write!(f, "coordinate {} of point {} is not an integer", coordinate, point),
+            LoadError::EdgeArity { edge, found } =>
+                write!(f, "edge {} has {} ends, 2 expected", edge, found),
+            LoadError::NonIntegerEdgeEnd { edge } =>
+                write!(f, "ends of edge {} should be non negative integers", edge),
+            LoadError::EdgeOutOfRange { edge, end, points } =>
+                write!(f, "edge {} references point {} but figure has only {} points", edge, end, points),
+            LoadError::FaceArity { face, found } =>
+                write!(f, "face {} has {} points, at least 3 expected", face, found),
+            LoadError::NonIntegerFaceIndex { face } =>
+                write!(f, "points of face {} should be non negative integers", face),
+            LoadError::FaceOutOfRange { face, point, points } =>
+                write!(f, "face {} references point {} but figure has only {} points", face, point, points),
+            LoadError::BadAttribute { item, key, expected } =>
+                write!(f, "'{}' of {} should be {}", key, item, expected),
+            LoadError::Obj { line, error } => write!(f, "OBJ error at line {}: {}", line, error.cause),
+        }
+    }
+}
+
+impl From<LoadError> for Error {
+    fn from(e: LoadError) -> Self {
+        return e.error();
+    }
+}
+
+/// Loads a figure whose points all have N coordinates.
+pub fn loadFigureN<const N: usize, T: Scalar>(json_source: &str) -> Result<Figure<N, T>, LoadError> {
+
+    fn toPoint<const N: usize, T: Scalar>(index: usize, point: &Value) -> Result<[T; N], LoadError> {
+        let coords = match point {
+            Value::Array(x) => x,
+            _ => return Err(LoadError::NotAnArray(format!("point {}", index)))
+        };
+        if coords.len() != N {
+            return Err(LoadError::CoordinateArity { point: index, expected: N, found: coords.len() });
+        }
+        let mut result: [T; N] = [T::from_i64(0); N];
+        for (i, c) in coords.iter().enumerate() {
+            let value = match c {
+                Value::Number(n) => match n.as_i64() {
+                    Some(v) => Some(T::from_i64(v)),
+                    None => n.as_f64().and_then(T::from_f64)
+                },
+                _ => return Err(LoadError::NotANumber { point: index, coordinate: i })
+            };
+            result[i] = match value {
+                Some(v) => v,
+                None => return Err(LoadError::NonIntegerCoordinate { point: index, coordinate: i })
+            };
+        }
+        return Ok(result);
+    }
+    fn bad(item: &str, key: &str, expected: &'static str) -> LoadError {
+        return LoadError::BadAttribute { item: item.to_string(), key: key.to_string(), expected };
+    }
+    /// Optional attribute of a point or edge object, read by `parse`.
+    fn attribute<A>(object: &serde_json::Map<String, Value>, item: &str, key: &str, expected: &'static str,
+                    parse: impl Fn(&Value) -> Option<A>) -> Result<Option<A>, LoadError> {
+        return match object.get(key) {
+            Some(value) => parse(value).map(Some).ok_or_else(|| bad(item, key, expected)),
+            None => Ok(None)
+        };
+    }
+    fn size(value: &Value) -> Option<f32> {
+        return value.as_f64().filter(|s| *s > 0.0).map(|s| s as f32);
+    }
+    /// Points are arrays of coordinates, or objects holding them under "coords" with a
+    /// "label", a "marker" and its "size".
+    fn loadPoints<const N: usize, T: Scalar>(figure:&mut Figure<N, T>, points: &Value) -> Result<(), LoadError> {
+        let content = match points {
+            Value::Array(content) => content,
+            _ => return Err(LoadError::NotAnArray("points".to_string()))
+        };
+        for (index, p) in content.iter().enumerate() {
+            let object = match p {
+                Value::Object(object) => object,
+                _ => {
+                    figure.add_point(toPoint(index, p)?);
+                    continue;
                 }
+            };
+            let item = format!("point {}", index);
+            match object.get("coords") {
+                Some(coords) => figure.add_point(toPoint(index, coords)?),
+                None => return Err(LoadError::MissingKey(format!("coords of {}", item)))
+            };
+            let default = PointStyle::default();
+            let style = PointStyle {
+                label: attribute(object, &item, "label", "a string", |v| v.as_str().map(|s| s.to_string()))?,
+                marker: attribute(object, &item, "marker", "circle, square or cross", |v| v.as_str().and_then(Marker::from_name))?,
+                size: attribute(object, &item, "size", "a positive number", size)?.unwrap_or(default.size),
+            };
+            if style != default {
+                figure.point_styles.insert(index, style);
             }
-            else {
-                Result::Err(Error { cause: "Array size error".to_string() })
-            },
-            _ => Result::Err(Error { cause: "Not an array".to_string() })
-        }
-    }
-    fn loadPoints(figure:&mut Figure<3>, points: &Value) {
-        match points {
-            Value::Array(content) => {
-                content.iter()
-                    .map(|x| toPoint(x))
-                    .for_each(|p | {
-                        let fx = match p {
-                            Result::Ok(x) => figure.add_point(x),
-                            Result::Err(e) => {
-                                panic!("{}", e.cause);
-                            },
-                        };
-                    });
-            },
-            _ => panic!("points not an array")
-        }
-    }
-    fn loadEdges(figure:&mut Figure<3>, edges: &Value) {
-        match edges {
-            Value::Array(content) => {
-                content.iter()
-                    .for_each(|edge | {
-                        match edge {
-                            Value::Array(content) => {
-                                if content.len() != 2 {
-                                    panic!("edge should have 2 ends")
-                                }
-                                let p1 = &content[0];
-                                let p2 = &content[1];
-                                match (p1, p2) {
-                                    (Value::Number(point1), Value::Number(point2)) => {
-                                        match (point1.as_u64(), point2.as_u64()) {
-                                            (Some(u1), Some(u2)) => {
-                                                figure.add_edge(u1 as usize, u2 as usize);
-                                            },
-                                            _ => panic!("end should be number")
-                                        }
-                                    },
-                                    _ => panic!("edge ends should be number")
-                                }
-                            },
-                            _ => panic!("edges not an array")
-                        }
-                    });
-            },
-            _ => panic!("edges not an array")
         }
+        Ok(())
     }
-    figure.points.clear();
-    figure.edges.clear();
-    let json_figure = serde_json::from_str::<Value>(&json_source).unwrap();
+    fn loadFaces<const N: usize, T>(figure:&mut Figure<N, T>, faces: &Value) -> Result<(), LoadError> {
+        let content = match faces {
+            Value::Array(content) => content,
+            _ => return Err(LoadError::NotAnArray("faces".to_string()))
+        };
+        for (index, face) in content.iter().enumerate() {
+            let points = match face {
+                Value::Array(points) => points,
+                _ => return Err(LoadError::NotAnArray(format!("face {}", index)))
+            };
+            if points.len() < 3 {
+                return Err(LoadError::FaceArity { face: index, found: points.len() });
+            }
+            let mut indexes: Vec<usize> = vec![];
+            for p in points.iter() {
+                match p.as_u64() {
+                    Some(i) => indexes.push(i as usize),
+                    None => return Err(LoadError::NonIntegerFaceIndex { face: index })
+                }
+            }
+            figure.add_face(&indexes);
+        }
+        Ok(())
+    }
+    fn loadEdges<const N: usize, T>(figure:&mut Figure<N, T>, edges: &Value) -> Result<(), LoadError> {
+        let content = match edges {
+            Value::Array(content) => content,
+            _ => return Err(LoadError::NotAnArray("edges".to_string()))
+        };
+        // Edges are pairs of point indexes, or objects holding them under "points" with a
+        // "color", a "width" and a "dash" pattern.
+        for (index, edge) in content.iter().enumerate() {
+            let item = format!("edge {}", index);
+            let (ends, style) = match edge {
+                Value::Array(ends) => (ends, EdgeStyle::default()),
+                Value::Object(object) => {
+                    let ends = match object.get("points") {
+                        Some(Value::Array(ends)) => ends,
+                        Some(_) => return Err(LoadError::NotAnArray(format!("points of {}", item))),
+                        None => return Err(LoadError::MissingKey(format!("points of {}", item)))
+                    };
+                    let style = EdgeStyle {
+                        color: attribute(object, &item, "color", "a color like \"#ff8800\"", |v| v.as_str().and_then(parse_color))?,
+                        width: attribute(object, &item, "width", "a positive number", size)?,
+                        dash: attribute(object, &item, "dash", "solid, dashed or dotted", |v| v.as_str().and_then(Dash::from_name))?
+                            .unwrap_or_default(),
+                    };
+                    (ends, style)
+                },
+                _ => return Err(LoadError::NotAnArray(item))
+            };
+            if ends.len() != 2 {
+                return Err(LoadError::EdgeArity { edge: index, found: ends.len() });
+            }
+            match (ends[0].as_u64(), ends[1].as_u64()) {
+                (Some(u1), Some(u2)) => {
+                    figure.edges.push(Edge { points: (u1 as usize, u2 as usize), style });
+                },
+                _ => return Err(LoadError::NonIntegerEdgeEnd { edge: index })
+            }
+        }
+        Ok(())
+    }
+    let json_figure = serde_json::from_str::<Value>(&json_source)
+        .map_err(|e| LoadError::Syntax {
+            line: e.line(),
+            column: e.column(),
+            error: Error { cause: e.to_string() }
+        })?;
+    let mut figure: Figure<N, T> = Figure::new();
     match json_figure {
         Value::Object(x) => {
-            let points = x.get("points");
-            match points {
-                Some(pts) => loadPoints(figure, pts),
-                None => panic!("not an object")
+            match x.get("points") {
+                Some(pts) => loadPoints(&mut figure, pts)?,
+                None => return Err(LoadError::MissingKey("points".to_string()))
             }
 
-            let edges = x.get("edges");
-            match edges {
-                Some(pts) => loadEdges(figure, pts),
-                None => panic!("not an object")
+            match x.get("edges") {
+                Some(edges) => loadEdges(&mut figure, edges)?,
+                None => return Err(LoadError::MissingKey("edges".to_string()))
             }
-            figure.edges.iter().for_each(|e|  println!("edge : {} to {}", e.points.0, e.points.1));
+
+            if let Some(faces) = x.get("faces") {
+                loadFaces(&mut figure, faces)?;
+            }
+            for issue in figure.validate() {
+                match issue {
+                    Issue::DanglingEdge { edge, end } =>
+                        return Err(LoadError::EdgeOutOfRange { edge, end, points: figure.points.len() }),
+                    Issue::DanglingFace { face, point } =>
+                        return Err(LoadError::FaceOutOfRange { face, point, points: figure.points.len() }),
+                    _ => {}
+                }
+            }
+            Ok(figure)
         }
-        _ => panic!("not an object")
+        _ => Err(LoadError::NotAnObject)
     }
 }
 
+/// Coordinates are read as `T`: loading as `i64` rejects non integer coordinates.
+pub fn loadFigure<T: Scalar>(json_source: &str) -> Result<Figure<3, T>, LoadError> {
+    return loadFigureN(json_source);
+}
+
+/// Number of coordinates of the first point of a figure, 3 when it has no points.
+pub fn figureDimension(json_source: &str) -> Result<usize, LoadError> {
+    let json_figure = serde_json::from_str::<Value>(&json_source)
+        .map_err(|e| LoadError::Syntax {
+            line: e.line(),
+            column: e.column(),
+            error: Error { cause: e.to_string() }
+        })?;
+    let points = match &json_figure {
+        Value::Object(x) => match x.get("points") {
+            Some(pts) => pts,
+            None => return Err(LoadError::MissingKey("points".to_string()))
+        },
+        _ => return Err(LoadError::NotAnObject)
+    };
+    return match points {
+        Value::Array(content) => match content.first() {
+            Some(Value::Array(coords)) => Ok(coords.len()),
+            Some(Value::Object(point)) => match point.get("coords") {
+                Some(Value::Array(coords)) => Ok(coords.len()),
+                _ => Err(LoadError::NotAnArray("coords of point 0".to_string()))
+            },
+            Some(_) => Err(LoadError::NotAnArray("point 0".to_string())),
+            None => Ok(3)
+        },
+        _ => Err(LoadError::NotAnArray("points".to_string()))
+    };
+}
+
+/// Writes a figure in the .fig JSON format read by `loadFigure`.
+pub fn saveFigure<const N: usize, T: Scalar>(figure: &Figure<N, T>) -> String {
+    // Points and edges without attributes keep the historic array layout.
+    let points: Vec<String> = figure.points.iter().enumerate()
+        .map(|(index, p)| {
+            let coords: Vec<String> = p.coords.iter().map(|c| c.to_string()).collect();
+            let style = match figure.point_styles.get(&index) {
+                Some(style) => style,
+                None => return format!("        [{}]", coords.join(", "))
+            };
+            let mut attributes = vec![format!("\"coords\": [{}]", coords.join(", "))];
+            if let Some(label) = &style.label {
+                attributes.push(format!("\"label\": {}", Value::String(label.clone())));
+            }
+            if let Some(marker) = style.marker {
+                attributes.push(format!("\"marker\": \"{}\"", marker.name()));
+            }
+            if style.size != PointStyle::default().size {
+                attributes.push(format!("\"size\": {}", style.size));
+            }
+            format!("        {{{}}}", attributes.join(", "))
+        })
+        .collect();
+    let edges: Vec<String> = figure.edges.iter()
+        .map(|e| {
+            if e.style == EdgeStyle::default() {
+                return format!("        [{}, {}]", e.points.0, e.points.1);
+            }
+            let mut attributes = vec![format!("\"points\": [{}, {}]", e.points.0, e.points.1)];
+            if let Some(color) = e.style.color {
+                attributes.push(format!("\"color\": \"{}\"", color_name(color)));
+            }
+            if let Some(width) = e.style.width {
+                attributes.push(format!("\"width\": {}", width));
+            }
+            if e.style.dash != Dash::Solid {
+                attributes.push(format!("\"dash\": \"{}\"", e.style.dash.name()));
+            }
+            format!("        {{{}}}", attributes.join(", "))
+        })
+        .collect();
+    // Faces are optional, figures without them keep the historic layout.
+    let faces = if figure.faces.is_empty() {
+        String::new()
+    } else {
+        let lines: Vec<String> = figure.faces.iter()
+            .map(|f| {
+                let indexes: Vec<String> = f.points.iter().map(|i| i.to_string()).collect();
+                format!("        [{}]", indexes.join(", "))
+            })
+            .collect();
+        format!(",\n   \"faces\": [\n{}\n   ]", lines.join(",\n"))
+    };
+    return format!("{{\n   \"points\": [\n{}\n   ],\n   \"edges\": [\n{}\n   ]{}\n}}\n",
+                   points.join(",\n"),
+                   edges.join(",\n"),
+                   faces);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,16 +701,13 @@ mod tests {
     #[test]
     fn test1() {
         println!("tests");
-        let mut f : Figure<3> = Figure {
-            points: vec![],
-            edges: vec![]
-        };
+        let mut f : Figure<3, i64> = Figure::new();
         f.add_point([3, 4, 5]);
         f.add_point([5, 7, 11]);
         let fig =  f.add_edge(0, 1);
         let e: &Edge = &fig.edges[0];
-        let p: (&Point<3>, &Point<3>) = fig.edge_points(e);
-        let p1 : &Point<3> = p.0;
+        let p: (&Point<3, i64>, &Point<3, i64>) = fig.edge_points(e);
+        let p1 : &Point<3, i64> = p.0;
         let p2 = p.1;
         assert_eq!(3, p1.coords[0]);
         assert_eq!(11, p2.coords[2]);
@@ -182,14 +725,223 @@ mod tests {
                   [0,1], [1,2], [2,0]
             ]
         }";
-        let mut fig: Figure<3> = Figure {
-            points: vec![],
-            edges: vec![]
-        };
-        loadFigure(&figureDesc, &mut fig);
+        let fig: Figure<3> = loadFigure(&figureDesc).unwrap();
         assert_eq!(3, fig.points.len());
         assert_eq!(3, fig.edges.len());
     }
+
+    #[test]
+    fn rotate() {
+        let mut f : Figure<3, i64> = Figure::new();
+        f.add_point([10, 0, 0]);
+        f.add_point([30, 0, 0]);
+        f.add_point([20, 10, 0]);
+        f.add_point([20, -10, 0]);
+        assert_eq!([20.0, 0.0, 0.0], f.centroid().coords);
+
+        let r = f.rotated(std::f64::consts::FRAC_PI_2, 0.0);
+        assert_eq!(f.centroid().coords, r.centroid().coords);
+        let expected = [[20.0, 0.0, 10.0], [20.0, 0.0, -10.0], [20.0, 10.0, 0.0], [20.0, -10.0, 0.0]];
+        for (p, e) in r.points.iter().zip(expected.iter()) {
+            for i in 0..3 {
+                assert!((p.coords[i] - e[i]).abs() < 1e-9, "{:?} != {:?}", p.coords, e);
+            }
+        }
+        let r = f.rotated(0.0, std::f64::consts::FRAC_PI_2);
+        assert!((r.points[2].coords[2] - 10.0).abs() < 1e-9);
+        assert!(r.points[2].coords[1].abs() < 1e-9);
+    }
+
+    #[test]
+    fn validate() {
+        let mut f : Figure<3, i64> = Figure::new();
+        f.add_point([0, 0, 0]);
+        f.add_point([1, 0, 0]);
+        f.add_point([0, 1, 0]);
+        f.add_point([0, 0, 1]);
+        f.add_edge(0, 1);
+        f.add_edge(1, 2);
+        assert_eq!(vec![Issue::UnreferencedPoint { point: 3 }], f.validate());
+
+        f.add_edge(2, 1);
+        f.add_edge(2, 2);
+        f.add_edge(0, 99);
+        let issues = f.validate();
+        assert_eq!(vec![
+            Issue::DuplicateEdge { edge: 2, first: 1 },
+            Issue::SelfLoop { edge: 3 },
+            Issue::DanglingEdge { edge: 4, end: 99 },
+            Issue::UnreferencedPoint { point: 3 },
+        ], issues);
+        assert_eq!(1, issues.iter().filter(|i| i.is_fatal()).count());
+    }
+
+    #[test]
+    fn save_round_trip() {
+        fn round_trip<const N: usize>(source: &str, path: &std::path::Path) {
+            let fig: Figure<N> = loadFigureN(source).unwrap();
+            let saved = saveFigure(&fig);
+            let reloaded: Figure<N> = loadFigureN(&saved).unwrap();
+            assert_eq!(fig.points, reloaded.points, "points of {:?}", path);
+            assert_eq!(fig.edges, reloaded.edges, "edges of {:?}", path);
+            assert_eq!(fig.faces, reloaded.faces, "faces of {:?}", path);
+            assert_eq!(saved, saveFigure(&reloaded));
+            let integers: Figure<N, i64> = loadFigureN(source).unwrap();
+            assert_eq!(saved, saveFigure(&integers));
+        }
+        for entry in std::fs::read_dir("example").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(false, |e| e == "fig") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                match figureDimension(&source).unwrap() {
+                    3 => round_trip::<3>(&source, &path),
+                    4 => round_trip::<4>(&source, &path),
+                    n => panic!("unexpected dimension {} of {:?}", n, path)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dimensions() {
+        let source = "{ \"points\": [[1, 2, 3, 4], [5, 6, 7, 8]], \"edges\": [[0, 1]] }";
+        assert_eq!(4, figureDimension(source).unwrap());
+        let fig: Figure<4, i64> = loadFigureN(source).unwrap();
+        assert_eq!([5, 6, 7, 8], fig.points[1].coords);
+        match loadFigure::<i64>(source) {
+            Err(LoadError::CoordinateArity { point, expected, found }) => {
+                assert_eq!((0, 3, 4), (point, expected, found));
+            },
+            _ => panic!("arity error expected")
+        }
+        assert_eq!(3, figureDimension("{ \"points\": [], \"edges\": [] }").unwrap());
+        assert!(figureDimension("{ \"edges\": [] }").is_err());
+    }
+
+    #[test]
+    fn load_errors() {
+        match loadFigure::<i64>("{ \"points\": [[1, 2, 3],\n [1, 2 3]], \"edges\": [] }") {
+            Err(LoadError::Syntax { line, column, .. }) => {
+                assert_eq!(2, line);
+                assert_eq!(8, column);
+            },
+            _ => panic!("syntax error expected")
+        }
+        match loadFigure::<i64>("{ \"points\": [[1, 2, 3], [1, 2]], \"edges\": [] }") {
+            Err(LoadError::CoordinateArity { point, expected, found }) => {
+                assert_eq!((1, 3, 2), (point, expected, found));
+            },
+            _ => panic!("arity error expected")
+        }
+        match loadFigure::<i64>("{ \"points\": [[1, 2.5, 3]], \"edges\": [] }") {
+            Err(LoadError::NonIntegerCoordinate { point, coordinate }) => {
+                assert_eq!((0, 1), (point, coordinate));
+            },
+            _ => panic!("non integer error expected")
+        }
+        let floats: Figure<3> = loadFigure("{ \"points\": [[1, 2.5, 3]], \"edges\": [] }").unwrap();
+        assert_eq!([1.0, 2.5, 3.0], floats.points[0].coords);
+        match loadFigure::<f64>("{ \"points\": [[1, \"2\", 3]], \"edges\": [] }") {
+            Err(LoadError::NotANumber { point, coordinate }) => {
+                assert_eq!((0, 1), (point, coordinate));
+            },
+            _ => panic!("not a number error expected")
+        }
+        match loadFigure::<i64>("{ \"points\": [[1, 2, 3]] }") {
+            Err(LoadError::MissingKey(key)) => assert_eq!("edges", key),
+            _ => panic!("missing key error expected")
+        }
+        match loadFigure::<i64>("{ \"points\": [[1, 2, 3], [3, 2, 1]], \"edges\": [[0, 1], [1, 2]] }") {
+            Err(e) => {
+                match e {
+                    LoadError::EdgeOutOfRange { edge, end, points } => assert_eq!((1, 2, 2), (edge, end, points)),
+                    _ => panic!("out of range error expected")
+                }
+                assert_eq!("edge 1 references point 2 but figure has only 2 points", e.error().cause);
+            },
+            _ => panic!("out of range error expected")
+        }
+    }
+
+    #[test]
+    fn faces() {
+        let source = "{ \"points\": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], \"edges\": [[0, 1], [1, 2], [2, 0]], \"faces\": [[0, 1, 2]] }";
+        let f: Figure<3, i64> = loadFigure(source).unwrap();
+        assert_eq!(vec![Face { points: vec![0, 1, 2] }], f.faces);
+        assert_eq!(3, f.face_points(&f.faces[0]).len());
+        let saved = saveFigure(&f);
+        assert!(saved.contains("\"faces\": [\n        [0, 1, 2]\n   ]"), "{}", saved);
+        assert_eq!(f.faces, loadFigure::<i64>(&saved).unwrap().faces);
+
+        let without: Figure<3, i64> = loadFigure("{ \"points\": [], \"edges\": [] }").unwrap();
+        assert!(without.faces.is_empty());
+        assert!(!saveFigure(&without).contains("faces"));
+
+        match loadFigure::<i64>("{ \"points\": [[0, 0, 0]], \"edges\": [], \"faces\": [[0, 0]] }") {
+            Err(LoadError::FaceArity { face, found }) => assert_eq!((0, 2), (face, found)),
+            _ => panic!("face arity error expected")
+        }
+        match loadFigure::<i64>("{ \"points\": [[0, 0, 0]], \"edges\": [], \"faces\": [[0, -1, 0]] }") {
+            Err(LoadError::NonIntegerFaceIndex { face }) => assert_eq!(0, face),
+            _ => panic!("face index error expected")
+        }
+        match loadFigure::<i64>("{ \"points\": [[0, 0, 0]], \"edges\": [], \"faces\": [[0, 0, 0], [0, 5, 0]] }") {
+            Err(LoadError::FaceOutOfRange { face, point, points }) => assert_eq!((1, 5, 1), (face, point, points)),
+            _ => panic!("face out of range error expected")
+        }
+    }
+
+    #[test]
+    fn styles() {
+        let source = "{ \"points\": [[0, 0, 0], {\"coords\": [1, 0, 0], \"label\": \"B \\\"end\\\"\", \"marker\": \"cross\"}],
+                        \"edges\": [{\"points\": [0, 1], \"color\": \"#ff8800\", \"width\": 3.5, \"dash\": \"dotted\"}, [1, 0]] }";
+        let f: Figure<3, i64> = loadFigure(source).unwrap();
+        assert_eq!([1, 0, 0], f.points[1].coords);
+        assert_eq!(Some(&PointStyle { label: Some("B \"end\"".to_string()), marker: Some(Marker::Cross), size: 6.0 }),
+                   f.point_styles.get(&1));
+        assert_eq!(1, f.point_styles.len());
+        assert_eq!(EdgeStyle { color: Some([255, 136, 0, 255]), width: Some(3.5), dash: Dash::Dotted },
+                   f.edges[0].style);
+        assert_eq!(EdgeStyle::default(), f.edges[1].style);
+
+        let saved = saveFigure(&f);
+        assert!(saved.contains("        [0, 0, 0],\n"), "{}", saved);
+        assert!(saved.contains("{\"points\": [0, 1], \"color\": \"#ff8800\", \"width\": 3.5, \"dash\": \"dotted\"}"), "{}", saved);
+        let reloaded: Figure<3, i64> = loadFigure(&saved).unwrap();
+        assert_eq!(f.edges, reloaded.edges);
+        assert_eq!(f.point_styles, reloaded.point_styles);
+        // Translucent colors are kept as written, not through premultiplied channels.
+        let translucent: Figure<3, i64> = loadFigure(&source.replace("#ff8800", "#40c0ff80")).unwrap();
+        assert_eq!(Some([0x40, 0xc0, 0xff, 0x80]), translucent.edges[0].style.color);
+        assert!(saveFigure(&translucent).contains("\"color\": \"#40c0ff80\""));
+        let reloaded: Figure<3, i64> = loadFigure(&saveFigure(&translucent)).unwrap();
+        assert_eq!(translucent.edges, reloaded.edges);
+        assert_eq!(4, figureDimension("{ \"points\": [{\"coords\": [1, 2, 3, 4]}], \"edges\": [] }").unwrap());
+
+        let errors = [
+            ("{ \"points\": [[0, 0, 0]], \"edges\": [{\"points\": [0, 0], \"color\": \"orange\"}] }", "edge 0", "color"),
+            ("{ \"points\": [[0, 0, 0]], \"edges\": [{\"points\": [0, 0], \"width\": -1}] }", "edge 0", "width"),
+            ("{ \"points\": [[0, 0, 0]], \"edges\": [{\"points\": [0, 0], \"dash\": \"wavy\"}] }", "edge 0", "dash"),
+            ("{ \"points\": [{\"coords\": [0, 0, 0], \"marker\": \"star\"}], \"edges\": [] }", "point 0", "marker"),
+            ("{ \"points\": [{\"coords\": [0, 0, 0], \"label\": 3}], \"edges\": [] }", "point 0", "label"),
+        ];
+        for (source, expected_item, expected_key) in errors {
+            match loadFigure::<i64>(source) {
+                Err(LoadError::BadAttribute { item, key, .. }) => assert_eq!((expected_item, expected_key), (item.as_str(), key.as_str())),
+                other => panic!("attribute error expected, got {:?}", other.err())
+            }
+        }
+        assert!(matches!(loadFigure::<i64>("{ \"points\": [{\"label\": \"A\"}], \"edges\": [] }"), Err(LoadError::MissingKey(_))));
+        assert_eq!(None, parse_color("#12345"));
+        assert_eq!(Some([255, 0, 0, 128]), parse_color("#ff000080"));
+        assert_eq!("#0a0b0c", color_name(parse_color("#0A0B0C").unwrap()));
+        for d in Dash::ALL {
+            assert_eq!(Some(d), Dash::from_name(d.name()));
+        }
+        for m in Marker::ALL {
+            assert_eq!(Some(m), Marker::from_name(m.name()));
+        }
+    }
 }
 pub trait Distance<T, const N: usize> {
     fn calc(from: T) -> f64;
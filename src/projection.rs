@@ -1,53 +1,387 @@
-use eframe::epaint::{ Color32, Pos2 };
-use std::ops::Div;
+use eframe::epaint::{ Color32, Pos2, Rect, Vec2 };
 
-use crate::points::Point;
+use crate::camera::{cross, dot, normalize, sub, Camera};
+use crate::hidden::HiddenLines;
+use crate::matrix::Vector;
+use crate::points::{Point, Scalar};
+use crate::shading::Shading;
+use crate::stereo::StereoRig;
+
+/// Stroke used for edges, shared by the GUI and the exports.
+pub const EDGE_WIDTH: f32 = 2.4;
+/// Dash and gap lengths of hidden and dashed edges.
+pub const DASH: [f32; 2] = [6.0, 4.0];
+/// Distance between the dots of dotted edges, each dot as wide as the edge.
+pub const DOT_SPACING: f32 = 6.0;
+/// Width of the cross marker strokes.
+pub const CROSS_WIDTH: f32 = 1.5;
+/// Font size of point labels.
+pub const LABEL_SIZE: f32 = 14.0;
+/// Panel fill of the default dark egui theme.
+pub const BACKGROUND: Color32 = Color32::from_gray(27);
+/// Drawing area shared by the screens of the viewer and of the exports.
+pub const DEFAULT_AREA: [u32; 2] = [900, 700];
+
+/// How a screen maps the figure onto its plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionMode {
+    /// Through the camera, with foreshortening.
+    Perspective,
+    /// Orthographic views along the z, y and x axes.
+    Front,
+    Top,
+    Side,
+    /// Axonometric views, all axes equally foreshortened or the depth axis halved.
+    Isometric,
+    Dimetric,
+    /// Oblique views, the depth axis drawn at 45 degrees at full or half length.
+    Cavalier,
+    Cabinet,
+}
+
+impl ProjectionMode {
+    pub const ALL: [ProjectionMode; 8] = [
+        ProjectionMode::Perspective, ProjectionMode::Front, ProjectionMode::Top, ProjectionMode::Side,
+        ProjectionMode::Isometric, ProjectionMode::Dimetric, ProjectionMode::Cavalier, ProjectionMode::Cabinet,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProjectionMode::Perspective => "perspective",
+            ProjectionMode::Front => "front",
+            ProjectionMode::Top => "top",
+            ProjectionMode::Side => "side",
+            ProjectionMode::Isometric => "isometric",
+            ProjectionMode::Dimetric => "dimetric",
+            ProjectionMode::Cavalier => "cavalier",
+            ProjectionMode::Cabinet => "cabinet",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ProjectionMode> {
+        return ProjectionMode::ALL.iter().find(|m| m.name() == name).copied();
+    }
+
+    /// World directions of the screen x and y axes for parallel projections, None for perspective.
+    pub fn axes(&self) -> Option<[[f64; 3]; 2]> {
+        // Right and up vectors of a camera looking at the origin from `toward_viewer`.
+        fn axonometric(toward_viewer: [f64; 3], up: [f64; 3]) -> [[f64; 3]; 2] {
+            let camera = Camera {
+                eye: Point { coords: toward_viewer },
+                target: Point { coords: [0.0, 0.0, 0.0] },
+                up: Point { coords: up },
+                ..Camera::default()
+            };
+            let (right, up, _) = camera.basis();
+            return [right, up];
+        }
+        fn oblique(depth: f64) -> [[f64; 3]; 2] {
+            let receding = depth * std::f64::consts::FRAC_1_SQRT_2;
+            return [[1.0, 0.0, receding], [0.0, 1.0, receding]];
+        }
+        let y_up = [0.0, 1.0, 0.0];
+        return match self {
+            ProjectionMode::Perspective => None,
+            ProjectionMode::Front => Some(axonometric([0.0, 0.0, -1.0], y_up)),
+            ProjectionMode::Top => Some(axonometric([0.0, 1.0, 0.0], [0.0, 0.0, 1.0])),
+            ProjectionMode::Side => Some(axonometric([1.0, 0.0, 0.0], y_up)),
+            ProjectionMode::Isometric => Some(axonometric([1.0, 1.0, -1.0], y_up)),
+            // x and y seen at the same scale, z at half of it.
+            ProjectionMode::Dimetric => Some(axonometric([1.0, 1.0, -(7f64).sqrt()], y_up)),
+            ProjectionMode::Cavalier => Some(oblique(1.0)),
+            ProjectionMode::Cabinet => Some(oblique(0.5)),
+        };
+    }
+}
+
+/// Part of the space kept when drawing edges in perspective.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clipping {
+    /// In front of the camera near plane.
+    Near,
+    /// Inside the field of view, between the near and far planes.
+    Frustum,
+}
+
+impl Clipping {
+    /// Planes as coefficients of homogeneous clip coordinates, positive inside.
+    fn planes(&self) -> &'static [[f64; 4]] {
+        const NEAR: [f64; 4] = [0.0, 0.0, 1.0, 1.0];
+        return match self {
+            Clipping::Near => &[NEAR],
+            Clipping::Frustum => &[
+                NEAR,
+                [0.0, 0.0, -1.0, 1.0],
+                [1.0, 0.0, 0.0, 1.0],
+                [-1.0, 0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0, 1.0],
+                [0.0, -1.0, 0.0, 1.0],
+            ],
+        };
+    }
+
+    /// Part of the segment from `a` to `b` inside all planes (Liang-Barsky).
+    pub fn clip(&self, a: [f64; 4], b: [f64; 4]) -> Option<([f64; 4], [f64; 4])> {
+        let dot = |plane: &[f64; 4], p: [f64; 4]| (0..4).map(|i| plane[i] * p[i]).sum::<f64>();
+        let (mut t0, mut t1) = (0.0, 1.0);
+        for plane in self.planes() {
+            let (da, db) = (dot(plane, a), dot(plane, b));
+            if da < 0.0 && db < 0.0 {
+                return None;
+            }
+            if da < 0.0 {
+                t0 = f64::max(t0, da / (da - db));
+            } else if db < 0.0 {
+                t1 = f64::min(t1, da / (da - db));
+            }
+            if t0 > t1 {
+                return None;
+            }
+        }
+        let at = |t: f64| [0, 1, 2, 3].map(|i| a[i] + t * (b[i] - a[i]));
+        return Some((at(t0), at(t1)));
+    }
+}
 
 pub struct Screen {
-    pub zoom: i64,
-    pub projectionpos: i64,
-    pub center: Point<2>,
+    /// Eye looking through this screen.
+    pub camera: Camera,
+    pub mode: ProjectionMode,
+    pub clipping: Clipping,
+    pub hidden_lines: HiddenLines,
+    pub shading: Shading,
     pub color: Color32,
     pub size: [u32; 2],
+    /// Top left corner of the viewport in the drawing area.
+    pub origin: [f64; 2],
 }
 
-pub trait Projection<const N: usize> {
-    fn project(&self, point: &Point<N>) -> Point<2>;
+impl Screen {
+    pub fn aspect(&self) -> f64 {
+        return self.size[0] as f64 / self.size[1] as f64;
+    }
+
+    pub fn viewport(&self) -> Rect {
+        return Rect::from_min_size(
+            Pos2::new(self.origin[0] as f32, self.origin[1] as f32),
+            Vec2::new(self.size[0] as f32, self.size[1] as f32));
+    }
+
+    /// Color of geometry drawn in `color` through this screen. It is multiplied by the screen
+    /// color, the anaglyph filter of the eye for overlaid stereo screens, which gives the same
+    /// result as `Anaglyph::filter`.
+    pub fn tint(&self, color: Color32) -> Color32 {
+        let channel = |c: u8, s: u8| ((c as u16 * s as u16) / 255) as u8;
+        let (c, s) = (color.to_array(), self.color.to_array());
+        return Color32::from_rgba_premultiplied(channel(c[0], s[0]), channel(c[1], s[1]), channel(c[2], s[2]), channel(c[3], s[3]));
+    }
+
+    /// How the edges are drawn behind faces. Edges drawn over shaded faces are hidden by them
+    /// even when the screen shows hidden lines, so that back edges don't cross the faces.
+    pub fn edge_hiding(&self) -> HiddenLines {
+        if self.hidden_lines == HiddenLines::Show && self.shading.fill.faces() {
+            return HiddenLines::Hide;
+        }
+        return self.hidden_lines;
+    }
+
+    /// Projection of a single point, None when it is behind the near plane of a perspective screen.
+    pub fn visible_point<T: Scalar>(&self, p: &Point<3, T>) -> Option<Point<2>> {
+        if self.mode.axes().is_none() && self.depth(p) < self.camera.near {
+            return None;
+        }
+        return Some(self.project(p));
+    }
+
+    /// How far in front of the screen a point is, along the direction the screen looks.
+    /// Parallel projections measure it from the camera target.
+    pub fn depth<T: Scalar>(&self, p: &Point<3, T>) -> f64 {
+        return match self.mode.axes() {
+            None => self.camera.to_view(p).coords[2],
+            Some([x, y]) => {
+                let p = [p.coords[0].to_f64(), p.coords[1].to_f64(), p.coords[2].to_f64()];
+                dot(sub(p, self.camera.target.coords), normalize(cross(x, y)))
+            }
+        };
+    }
+
+    /// Homogeneous coordinates of a point seen through the camera, before the perspective divide.
+    fn clip_coords<T: Scalar>(&self, p: &Point<3, T>) -> [f64; 4] {
+        let v = Vector { values: [p.coords[0].to_f64(), p.coords[1].to_f64(), p.coords[2].to_f64(), 1.0] };
+        return self.camera.view_projection(self.aspect()).apply(&v).values;
+    }
+
+    /// Pixels of homogeneous coordinates. `w` is the view depth, points closer than the near plane
+    /// are divided as if they were on it so they stay finite; `visible_point` tells them apart.
+    fn from_clip_coords(&self, c: [f64; 4]) -> Point<2> {
+        let w = c[3].max(self.camera.near);
+        return Point {
+            coords: [c[0] / w * self.size[0] as f64 / 2.0, c[1] / w * self.size[1] as f64 / 2.0]
+        };
+    }
+}
+
+/// Width and height covering the viewports of all the screens.
+pub fn drawing_size(screens: &[Screen]) -> [u32; 2] {
+    let width = screens.iter().map(|s| s.viewport().max.x).fold(0.0, f32::max).ceil();
+    let height = screens.iter().map(|s| s.viewport().max.y).fold(0.0, f32::max).ceil();
+    return [width as u32, height as u32];
+}
+
+/// The two overlaid stereo screens of the viewer, looking through `camera`.
+pub fn default_screens(camera: &Camera) -> [Screen; 2] {
+    let mut screens = [0, 1].map(|_| Screen {
+        camera: *camera,
+        mode: ProjectionMode::Perspective,
+        clipping: Clipping::Near,
+        hidden_lines: HiddenLines::Show,
+        shading: Shading::default(),
+        color: Color32::WHITE,
+        size: DEFAULT_AREA,
+        origin: [0.0, 0.0],
+    });
+    StereoRig::default().apply(&mut screens, camera, DEFAULT_AREA);
+    return screens;
+}
+
+pub trait Projection<const N: usize, T = f64> {
+    fn project(&self, point: &Point<N, T>) -> Point<2>;
+
+    /// Projects the visible part of the segment from `a` to `b`, None when nothing is visible.
+    fn project_segment(&self, a: &Point<N, T>, b: &Point<N, T>) -> Option<[Point<2>; 2]> {
+        return Some([self.project(a), self.project(b)]);
+    }
 }
 
 pub trait ToRealScreen {
     fn place(&self, point2D: &Point<2>) -> Pos2;
 }
 
-impl<'a, const N: usize> Projection<N> for Screen {
-    fn project(&self, point: &Point<N>) -> Point<2> {
-        fn transform_coord(screen: &Screen, distance: i64, v: i64) -> i64 {
-            return (v * screen.zoom).div(screen.zoom + distance - screen.projectionpos);
+impl<T: Scalar> Projection<3, T> for Screen {
+    /// Pixels from the viewport center, y going up. Parallel projections are centered on the
+    /// camera target and keep the scale the perspective has at the target distance.
+    fn project(&self, point: &Point<3, T>) -> Point<2> {
+        if let Some(axes) = self.mode.axes() {
+            let pixel = self.camera.pixel_size(self.size[1]);
+            let relative: Vec<f64> = (0..3).map(|i| point.coords[i].to_f64() - self.camera.target.coords[i]).collect();
+            let along = |axis: [f64; 3]| (0..3).map(|i| axis[i] * relative[i]).sum::<f64>() / pixel;
+            return Point { coords: [along(axes[0]), along(axes[1])] };
         }
-        fn apply_tranform<const N: usize>(screen: &Screen, p: &Point<N>, n: usize, v: i64) -> i64 {
-            let mut result = v;
-            for i in 2..n {
-                result = transform_coord(screen, p.coords[i], result);
-            }
-            return result;
+        return self.from_clip_coords(self.clip_coords(point));
+    }
+
+    /// Perspective segments are cut by the clipping planes instead of going through the eye.
+    fn project_segment(&self, a: &Point<3, T>, b: &Point<3, T>) -> Option<[Point<2>; 2]> {
+        if self.mode.axes().is_some() {
+            return Some([self.project(a), self.project(b)]);
         }
-        let x1 = apply_tranform(self, point, N, point.coords[0] - self.center.coords[0]);
-        let x2 = apply_tranform(self, point, N, point.coords[1] - self.center.coords[1]);
-        Point { coords: [x1, x2] }
+        let (a, b) = self.clipping.clip(self.clip_coords(a), self.clip_coords(b))?;
+        return Some([self.from_clip_coords(a), self.from_clip_coords(b)]);
     }
 }
 
 impl ToRealScreen for Screen {
     fn place(&self, point_2d: &Point<2>) -> Pos2 {
-        let abs_point: Point<2> = Point {
-            coords: [
-                point_2d.coords[0] + self.center.coords[0],
-                point_2d.coords[1] + self.center.coords[1],
-            ],
-        };
         return Pos2::new(
-            (abs_point.coords[0] + (self.size[0] as i64 / 2)) as f32,
-            ((self.size[1] as i64 / 2) - abs_point.coords[1]) as f32,
+            (self.origin[0] + point_2d.coords[0] + (self.size[0] as f64 / 2.0)) as f32,
+            (self.origin[1] + (self.size[1] as f64 / 2.0) - point_2d.coords[1]) as f32,
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(mode: ProjectionMode) -> Screen {
+        return Screen {
+            camera: Camera::default(),
+            mode,
+            clipping: Clipping::Near,
+            hidden_lines: HiddenLines::Show,
+            shading: Shading::default(),
+            color: Color32::WHITE,
+            size: DEFAULT_AREA,
+            origin: [0.0, 0.0],
+        };
+    }
+
+    fn project(s: &Screen, coords: [f64; 3]) -> [f64; 2] {
+        let p: Point<3> = Point { coords };
+        return s.project(&p).coords;
+    }
+
+    fn length(v: [f64; 2]) -> f64 {
+        return (v[0] * v[0] + v[1] * v[1]).sqrt();
+    }
+
+    #[test]
+    fn orthographic() {
+        let front = screen(ProjectionMode::Front);
+        let scale = 1.0 / front.camera.pixel_size(DEFAULT_AREA[1]);
+        assert_eq!(project(&front, [10.0, 20.0, 0.0]), project(&front, [10.0, 20.0, 300.0]));
+        let p = project(&front, [10.0, 20.0, 300.0]);
+        assert!((p[0] - 10.0 * scale).abs() < 1e-9 && (p[1] - 20.0 * scale).abs() < 1e-9);
+
+        let top = project(&screen(ProjectionMode::Top), [10.0, 20.0, 30.0]);
+        assert!((top[0] - 10.0 * scale).abs() < 1e-9 && (top[1] - 30.0 * scale).abs() < 1e-9);
+        let side = project(&screen(ProjectionMode::Side), [10.0, 20.0, 30.0]);
+        assert!((side[0] - 30.0 * scale).abs() < 1e-9 && (side[1] - 20.0 * scale).abs() < 1e-9);
+    }
+
+    #[test]
+    fn axonometric() {
+        let iso = screen(ProjectionMode::Isometric);
+        let axes = [[100.0, 0.0, 0.0], [0.0, 100.0, 0.0], [0.0, 0.0, 100.0]].map(|a| project(&iso, a));
+        assert!((length(axes[0]) - length(axes[1])).abs() < 1e-9);
+        assert!((length(axes[0]) - length(axes[2])).abs() < 1e-9);
+        // Vertical axis up, depth going up and to the right.
+        assert!(axes[1][0].abs() < 1e-9 && axes[1][1] > 0.0);
+        assert!(axes[2][0] > 0.0 && axes[2][1] > 0.0);
+
+        let di = screen(ProjectionMode::Dimetric);
+        let axes = [[100.0, 0.0, 0.0], [0.0, 100.0, 0.0], [0.0, 0.0, 100.0]].map(|a| project(&di, a));
+        assert!((length(axes[0]) - length(axes[1])).abs() < 1e-9);
+        assert!((length(axes[0]) - 2.0 * length(axes[2])).abs() < 1e-9);
+
+        let cavalier = project(&screen(ProjectionMode::Cavalier), [0.0, 0.0, 100.0]);
+        let cabinet = project(&screen(ProjectionMode::Cabinet), [0.0, 0.0, 100.0]);
+        assert!((length(cavalier) - 2.0 * length(cabinet)).abs() < 1e-9);
+        assert!((cavalier[0] - cavalier[1]).abs() < 1e-9);
+        assert_eq!(project(&screen(ProjectionMode::Cabinet), [10.0, 20.0, 0.0]),
+                   project(&screen(ProjectionMode::Front), [10.0, 20.0, 0.0]));
+
+        for m in ProjectionMode::ALL {
+            assert_eq!(Some(m), ProjectionMode::from_name(m.name()));
+        }
+    }
+
+    #[test]
+    fn clipping() {
+        let s = screen(ProjectionMode::Perspective);
+        let p = |coords: [f64; 3]| -> Point<3> { Point { coords } };
+        // Through the eye plane: cut at the near plane instead of mirrored.
+        let [a, b] = s.project_segment(&p([0.0, 0.0, 0.0]), &p([0.0, 100.0, -600.0])).unwrap();
+        assert_eq!(s.project(&p([0.0, 0.0, 0.0])), a);
+        assert!(b.coords[1] > 0.0 && b.coords[1].is_finite());
+        // Exactly on the eye plane, then entirely behind the camera.
+        assert!(s.project_segment(&p([0.0, 0.0, -500.0]), &p([10.0, 0.0, -500.0])).is_none());
+        assert!(s.project_segment(&p([0.0, 0.0, -600.0]), &p([10.0, 0.0, -700.0])).is_none());
+        // Single points there still project to finite coordinates, but are not visible.
+        for z in [-500.0, -700.0] {
+            let behind = p([10.0, 20.0, z]);
+            assert!(s.project(&behind).coords.iter().all(|c| c.is_finite()));
+            assert!(s.visible_point(&behind).is_none());
+        }
+
+        // Outside the field of view only the frustum cuts.
+        let wide = [p([-1000.0, 0.0, 0.0]), p([1000.0, 0.0, 0.0])];
+        let [a, _] = s.project_segment(&wide[0], &wide[1]).unwrap();
+        assert!(a.coords[0] < -(DEFAULT_AREA[0] as f64));
+        let frustum = Screen { clipping: Clipping::Frustum, ..s };
+        let [a, b] = frustum.project_segment(&wide[0], &wide[1]).unwrap();
+        assert!((a.coords[0] + DEFAULT_AREA[0] as f64 / 2.0).abs() < 1e-6);
+        assert!((b.coords[0] - DEFAULT_AREA[0] as f64 / 2.0).abs() < 1e-6);
+        assert!(frustum.project_segment(&p([0.0, 0.0, 20000.0]), &p([10.0, 0.0, 20000.0])).is_none());
+    }
+}
@@ -0,0 +1,382 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use eframe::epaint::{Color32, Pos2, Rect, Vec2};
+
+use crate::hidden::{edge_pieces, figure_runs};
+use crate::points::{Dash, Figure, Marker};
+use crate::projection::{drawing_size, Screen, ToRealScreen, BACKGROUND, CROSS_WIDTH, DASH, DOT_SPACING, EDGE_WIDTH};
+use crate::shading::shaded_faces;
+
+/// Nearness difference below which a line on a face is still drawn over it, relative to the
+/// nearness of the face but never less than its value at the camera target. Wide lines cover
+/// pixels where sloped faces are a bit nearer than the line.
+const LINE_DEPTH_TOLERANCE: f64 = 1e-2;
+
+/// How lines are drawn by the rasterizer.
+#[derive(Debug, Clone, Copy)]
+pub struct RasterOptions {
+    /// Width in pixels of the edges that don't set their own.
+    pub line_width: f32,
+    pub antialias: bool,
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        return RasterOptions { line_width: EDGE_WIDTH, antialias: true };
+    }
+}
+
+/// RGBA image drawn on the CPU, with a depth buffer.
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    /// Rows from the top, premultiplied colors.
+    pub pixels: Vec<Color32>,
+    /// Nearness of what each pixel shows, larger is nearer.
+    nearness: Vec<f64>,
+    /// Drawing outside of it is dropped.
+    pub clip: Rect,
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize, background: Color32) -> Raster {
+        return Raster {
+            width,
+            height,
+            pixels: vec![background; width * height],
+            nearness: vec![f64::NEG_INFINITY; width * height],
+            clip: Rect::from_min_max(Pos2::ZERO, Pos2::new(width as f32, height as f32)),
+        };
+    }
+
+    #[cfg(test)]
+    pub fn pixel(&self, x: usize, y: usize) -> Color32 {
+        return self.pixels[y * self.width + x];
+    }
+
+    /// Forgets the depths, so that the next drawing is not hidden by the previous one.
+    pub fn clear_depth(&mut self) {
+        self.nearness.iter_mut().for_each(|n| *n = f64::NEG_INFINITY);
+    }
+
+    /// Pixel range covered by `rect` and the clip rectangle, as (x range, y range).
+    fn pixels_in(&self, rect: Rect) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let r = rect.intersect(self.clip);
+        if !r.is_positive() {
+            return (0..0, 0..0);
+        }
+        let range = |min: f32, max: f32, size: usize| -> std::ops::Range<usize> {
+            (min.floor().max(0.0) as usize)..(max.ceil().max(0.0) as usize).min(size)
+        };
+        return (range(r.min.x, r.max.x, self.width), range(r.min.y, r.max.y, self.height));
+    }
+
+    /// Blends `color` over a pixel, transparent premultiplied colors add up as in egui.
+    fn blend(&mut self, x: usize, y: usize, color: Color32, coverage: f32) {
+        let index = y * self.width + x;
+        let under = self.pixels[index].to_array();
+        let over = color.to_array();
+        let keep = if color.a() == 0 { 1.0 } else { 1.0 - coverage * color.a() as f32 / 255.0 };
+        let mixed: Vec<u8> = (0..4)
+            .map(|i| (under[i] as f32 * keep + over[i] as f32 * coverage).round().min(255.0) as u8)
+            .collect();
+        self.pixels[index] = Color32::from_rgba_premultiplied(mixed[0], mixed[1], mixed[2], mixed[3]);
+    }
+
+    /// Fills a convex polygon, keeping the pixels where it is nearer than what is already drawn.
+    /// `nearness` is given for each point and must be linear on the screen.
+    pub fn fill_polygon(&mut self, points: &[Pos2], nearness: &[f64], color: Color32) {
+        for i in 1..points.len().saturating_sub(1) {
+            self.fill_triangle([points[0], points[i], points[i + 1]], [nearness[0], nearness[i], nearness[i + 1]], color);
+        }
+    }
+
+    fn fill_triangle(&mut self, p: [Pos2; 3], nearness: [f64; 3], color: Color32) {
+        let edge = |a: Pos2, b: Pos2, c: Pos2| -> f64 {
+            (b.x - a.x) as f64 * (c.y - a.y) as f64 - (b.y - a.y) as f64 * (c.x - a.x) as f64
+        };
+        let area = edge(p[0], p[1], p[2]);
+        if area == 0.0 {
+            return;
+        }
+        let bounds = Rect::from_points(&p);
+        let (xs, ys) = self.pixels_in(bounds);
+        for y in ys {
+            for x in xs.clone() {
+                let c = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                // Barycentric weights, all of the sign of the area inside the triangle.
+                let w = [edge(p[1], p[2], c) / area, edge(p[2], p[0], c) / area, edge(p[0], p[1], c) / area];
+                if w.iter().any(|w| *w < 0.0) {
+                    continue;
+                }
+                let n = w[0] * nearness[0] + w[1] * nearness[1] + w[2] * nearness[2];
+                let index = y * self.width + x;
+                if n > self.nearness[index] {
+                    self.nearness[index] = n;
+                    self.blend(x, y, color, 1.0);
+                }
+            }
+        }
+    }
+
+    /// True when a drawing at `nearness` is behind what the pixel shows, beyond the tolerance.
+    fn behind(&self, x: usize, y: usize, nearness: f64) -> bool {
+        let drawn = self.nearness[y * self.width + x];
+        return nearness < drawn - LINE_DEPTH_TOLERANCE * drawn.abs().max(1.0);
+    }
+
+    /// Draws a segment `width` pixels wide with round ends. With `nearness`, pixels behind faces
+    /// already drawn are skipped.
+    pub fn line(&mut self, ends: [Pos2; 2], nearness: Option<[f64; 2]>, width: f32, color: Color32, options: &RasterOptions) {
+        let half = (width / 2.0).max(0.5);
+        let (a, b) = (ends[0], ends[1]);
+        let (xs, ys) = self.pixels_in(Rect::from_two_pos(a, b).expand(half + 1.0));
+        let along = b - a;
+        let length = along.length_sq();
+        for y in ys {
+            for x in xs.clone() {
+                let c = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let t = if length == 0.0 { 0.0 } else { ((c - a).dot(along) / length).clamp(0.0, 1.0) };
+                let distance = (c - (a + along * t)).length();
+                let coverage = if options.antialias {
+                    (half + 0.5 - distance).clamp(0.0, 1.0)
+                } else if distance <= half {
+                    1.0
+                } else {
+                    0.0
+                };
+                if coverage == 0.0 {
+                    continue;
+                }
+                if let Some([from, to]) = nearness {
+                    if self.behind(x, y, from + (to - from) * t as f64) {
+                        continue;
+                    }
+                }
+                self.blend(x, y, color, coverage);
+            }
+        }
+    }
+
+    /// Draws the dashes of a segment, `pattern` gives their length and the distance between their
+    /// starts. Dotted lines have zero length dashes, drawn as dots by the round ends.
+    pub fn dashed_line(&mut self, ends: [Pos2; 2], nearness: Option<[f64; 2]>, pattern: [f32; 2],
+                       width: f32, color: Color32, options: &RasterOptions) {
+        let [dash, period] = pattern;
+        let along = ends[1] - ends[0];
+        let length = along.length();
+        let mut start = 0.0;
+        while start < length {
+            let end = (start + dash).min(length);
+            let t = |d: f32| if length == 0.0 { 0.0 } else { d / length };
+            let part = |n: [f64; 2]| [t(start), t(end)].map(|t| n[0] + (n[1] - n[0]) * t as f64);
+            self.line([ends[0] + along * t(start), ends[0] + along * t(end)], nearness.map(part), width, color, options);
+            start += period;
+        }
+    }
+
+    /// Fills a square centered on `center`, `size` pixels wide.
+    pub fn square(&mut self, center: Pos2, size: f32, color: Color32) {
+        let (xs, ys) = self.pixels_in(Rect::from_center_size(center, Vec2::splat(size)));
+        for y in ys {
+            for x in xs.clone() {
+                self.blend(x, y, color, 1.0);
+            }
+        }
+    }
+
+    pub fn encode_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut data: Vec<u8> = vec![];
+        let mut encoder = png::Encoder::new(&mut data, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let bytes: Vec<u8> = self.pixels.iter().flat_map(|p| p.to_srgba_unmultiplied()).collect();
+        writer.write_image_data(&bytes)?;
+        writer.finish()?;
+        return Ok(data);
+    }
+}
+
+/// Nearness stored in the depth buffer, linear on the screen: inverse depth in perspective.
+/// It is 1 at the camera target in every mode.
+fn nearness(screen: &Screen, depth: f64) -> f64 {
+    let distance = screen.camera.distance();
+    return match screen.mode.axes() {
+        None => distance / depth.max(screen.camera.near),
+        Some(_) => 1.0 - depth / distance,
+    };
+}
+
+/// Draws the figure through each screen like the GUI does, faces resolved by the depth buffer.
+/// Each screen is clipped to its viewport and has its own depths.
+pub fn render_raster(figure: &Figure<3>, screens: &[Screen], options: &RasterOptions) -> Raster {
+    let [width, height] = drawing_size(screens);
+    let mut raster = Raster::new(width as usize, height as usize, BACKGROUND);
+    for s in screens {
+        raster.clip = s.viewport();
+        raster.clear_depth();
+        if s.shading.fill.faces() {
+            for polygon in shaded_faces(s, figure) {
+                let points: Vec<Pos2> = polygon.points.iter().map(|p| s.place(p)).collect();
+                let near: Vec<f64> = polygon.depths.iter().map(|d| nearness(s, *d)).collect();
+                raster.fill_polygon(&points, &near, polygon.color);
+            }
+        }
+        if s.shading.fill.edges() {
+            let runs = figure_runs(s, figure);
+            for (e, runs) in figure.edges.iter().zip(runs.iter()) {
+                let color = s.tint(e.style.color32());
+                let width = e.style.width.unwrap_or(options.line_width);
+                for piece in edge_pieces(s, figure, e, runs) {
+                    let ends = [s.place(&piece.ends[0]), s.place(&piece.ends[1])];
+                    // Hidden pieces are drawn over the faces in front of them.
+                    let near = if piece.hidden { None } else { Some(piece.depths.map(|d| nearness(s, d))) };
+                    match piece.dash(e) {
+                        Dash::Solid => raster.line(ends, near, width, color, options),
+                        Dash::Dashed => raster.dashed_line(ends, near, [DASH[0], DASH[0] + DASH[1]], width, color, options),
+                        Dash::Dotted => raster.dashed_line(ends, near, [0.0, DOT_SPACING], width, color, options),
+                    }
+                }
+            }
+        }
+        draw_markers(&mut raster, s, figure, options);
+    }
+    return raster;
+}
+
+/// Markers of the points that have some, over everything like in the GUI and SVG.
+/// Labels need fonts, they are left to them.
+fn draw_markers(raster: &mut Raster, s: &Screen, figure: &Figure<3>, options: &RasterOptions) {
+    let color = s.tint(Color32::WHITE);
+    for (index, style) in figure.point_styles.iter() {
+        let p = &figure.points[*index];
+        let at = match s.visible_point(p) {
+            Some(at) => s.place(&at),
+            None => continue
+        };
+        let half = style.size / 2.0;
+        match style.marker {
+            Some(Marker::Circle) => raster.line([at, at], None, style.size, color, options),
+            Some(Marker::Square) => raster.square(at, style.size, color),
+            Some(Marker::Cross) => {
+                for (dx, dy) in [(half, half), (half, -half)] {
+                    let ends = [at - Vec2::new(dx, dy), at + Vec2::new(dx, dy)];
+                    raster.line(ends, None, CROSS_WIDTH, color, options);
+                }
+            },
+            None => {}
+        }
+    }
+}
+
+pub fn write_png(path: &Path, figure: &Figure<3>, screens: &[Screen], options: &RasterOptions) -> io::Result<()> {
+    let data = render_raster(figure, screens, options).encode_png()
+        .map_err(io::Error::other)?;
+    return fs::write(path, data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::generators::grid;
+    use crate::hidden::HiddenLines;
+    use crate::points::{loadFigure, PointStyle};
+    use crate::projection::{default_screens, Projection, ProjectionMode};
+    use crate::shading::Fill;
+    use crate::stereo::{StereoLayout, StereoRig};
+
+    #[test]
+    fn lines() {
+        let mut raster = Raster::new(20, 10, Color32::BLACK);
+        let options = RasterOptions { line_width: 2.0, antialias: true };
+        raster.line([Pos2::new(2.0, 5.0), Pos2::new(18.0, 5.0)], None, 2.0, Color32::WHITE, &options);
+        assert_eq!(Color32::WHITE, raster.pixel(10, 4));
+        assert_eq!(Color32::WHITE, raster.pixel(10, 5));
+        assert_eq!(Color32::BLACK, raster.pixel(10, 8));
+        // Pixels the edge of the line goes through are partly covered.
+        raster.line([Pos2::new(2.0, 1.5), Pos2::new(18.0, 1.5)], None, 2.0, Color32::WHITE, &options);
+        let edge = raster.pixel(10, 2).r();
+        assert!(edge > 0 && edge < 255, "{}", edge);
+
+        let mut aliased = Raster::new(20, 10, Color32::BLACK);
+        let options = RasterOptions { antialias: false, ..options };
+        aliased.line([Pos2::new(2.0, 1.5), Pos2::new(18.0, 1.5)], None, 2.0, Color32::WHITE, &options);
+        assert!(aliased.pixels.iter().all(|p| *p == Color32::BLACK || *p == Color32::WHITE));
+    }
+
+    #[test]
+    fn depth_buffer() {
+        let square = [Pos2::new(0.0, 0.0), Pos2::new(10.0, 0.0), Pos2::new(10.0, 10.0), Pos2::new(0.0, 10.0)];
+        for near_first in [true, false] {
+            let mut raster = Raster::new(10, 10, Color32::BLACK);
+            let mut faces = [(Color32::RED, 2.0), (Color32::BLUE, 1.0)];
+            if !near_first {
+                faces.reverse();
+            }
+            for (color, n) in faces {
+                raster.fill_polygon(&square, &[n; 4], color);
+            }
+            assert!(raster.pixels.iter().all(|p| *p == Color32::RED));
+            // Lines on the near face are drawn, lines behind it are not.
+            let options = RasterOptions::default();
+            raster.line([Pos2::new(0.0, 3.0), Pos2::new(10.0, 3.0)], Some([2.0, 2.0]), EDGE_WIDTH, Color32::WHITE, &options);
+            raster.line([Pos2::new(0.0, 7.0), Pos2::new(10.0, 7.0)], Some([1.0, 1.0]), EDGE_WIDTH, Color32::WHITE, &options);
+            assert_eq!(Color32::WHITE, raster.pixel(5, 3));
+            assert_eq!(Color32::RED, raster.pixel(5, 7));
+        }
+    }
+
+    #[test]
+    fn face_through_target() {
+        // Parallel nearness is 1 on the grid plane through the target, wide lines on its sloped
+        // faces must stay over them.
+        let figure = grid(200.0, 2);
+        let [mut s, _] = default_screens(&Camera::looking_at(&figure));
+        s.color = Color32::WHITE;
+        s.hidden_lines = HiddenLines::Hide;
+        s.shading.fill = Fill::Overlay;
+        for mode in [ProjectionMode::Isometric, ProjectionMode::Perspective] {
+            s.mode = mode;
+            let raster = render_raster(&figure, std::slice::from_ref(&s), &RasterOptions { line_width: 4.0, antialias: false });
+            for e in figure.edges.iter() {
+                let (a, b) = figure.edge_points(e);
+                let (a, b) = (s.place(&s.project(a)), s.place(&s.project(b)));
+                for i in 1..20 {
+                    let at = a + (b - a) * i as f32 / 20.0;
+                    assert_eq!(Color32::WHITE, raster.pixel(at.x as usize, at.y as usize), "{} {:?} {}", mode.name(), e.points, i);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cube() {
+        let mut figure: Figure<3> = loadFigure(&std::fs::read_to_string("example/cube.fig").unwrap()).unwrap();
+        let camera = Camera::looking_at(&figure);
+        let mut screens = default_screens(&camera);
+        StereoRig { layout: StereoLayout::Parallel, ..StereoRig::default() }.apply(&mut screens, &camera, [900, 700]);
+        for s in screens.iter_mut() {
+            s.shading.fill = Fill::Solid;
+        }
+        // A marker on a back corner is drawn over the faces.
+        let back = (0..figure.points.len()).max_by(|a, b| figure.points[*a].coords[2].total_cmp(&figure.points[*b].coords[2])).unwrap();
+        figure.point_styles.insert(back, PointStyle { marker: Some(Marker::Square), ..PointStyle::default() });
+        let raster = render_raster(&figure, &screens, &RasterOptions::default());
+        assert_eq!((900, 700), (raster.width, raster.height));
+        let marker = screens[0].place(&screens[0].project(&figure.points[back]));
+        assert_eq!(Color32::WHITE, raster.pixel(marker.x as usize, marker.y as usize));
+        let center = screens[0].viewport().center();
+        let face = shaded_faces(&screens[0], &figure).pop().unwrap().color;
+        assert_eq!(face, raster.pixel(center.x as usize, center.y as usize));
+        assert_eq!(BACKGROUND, raster.pixel(5, 5));
+
+        let png = raster.encode_png().unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((900, 700), (reader.info().width, reader.info().height));
+    }
+}
@@ -0,0 +1,200 @@
+use eframe::epaint::Color32;
+
+use crate::camera::{add, dot, normalize, sub, times};
+use crate::hidden::{corners, face_normal, toward_viewer};
+use crate::points::{Figure, Point};
+use crate::projection::{Projection, Screen};
+
+/// What a screen draws of the figure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fill {
+    /// Edges only, faces are ignored.
+    Wireframe,
+    /// Shaded faces only.
+    Solid,
+    /// Shaded faces with the edges drawn over them.
+    Overlay,
+}
+
+impl Fill {
+    pub const ALL: [Fill; 3] = [Fill::Wireframe, Fill::Solid, Fill::Overlay];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fill::Wireframe => "wireframe",
+            Fill::Solid => "solid",
+            Fill::Overlay => "overlay",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Fill> {
+        return Fill::ALL.iter().find(|f| f.name() == name).copied();
+    }
+
+    pub fn faces(&self) -> bool {
+        return *self != Fill::Wireframe;
+    }
+
+    pub fn edges(&self) -> bool {
+        return *self != Fill::Solid;
+    }
+}
+
+/// Flat Lambert shading of the faces of a screen.
+#[derive(Debug, Clone, Copy)]
+pub struct Shading {
+    pub fill: Fill,
+    /// World direction toward the light, any length.
+    pub light: [f64; 3],
+    /// Lightness of faces turned away from the light, from 0 to 1.
+    pub ambient: f64,
+}
+
+impl Default for Shading {
+    fn default() -> Self {
+        // From the upper left, in front of the default camera.
+        return Shading { fill: Fill::Wireframe, light: [-1.0, 1.0, -2.0], ambient: 0.25 };
+    }
+}
+
+impl Shading {
+    /// Lightness of a face with the unit `normal`.
+    pub fn lambert(&self, normal: [f64; 3]) -> f64 {
+        let diffuse = dot(normal, normalize(self.light)).max(0.0);
+        return self.ambient + (1.0 - self.ambient) * diffuse;
+    }
+}
+
+/// Scales the color channels, keeping the alpha so that additive colors stay additive.
+pub fn shade(color: Color32, lightness: f64) -> Color32 {
+    let channel = |c: u8| (c as f64 * lightness.clamp(0.0, 1.0)).round() as u8;
+    return Color32::from_rgba_premultiplied(channel(color.r()), channel(color.g()), channel(color.b()), color.a());
+}
+
+/// Projected face filled with a single color.
+#[derive(Debug)]
+pub struct Polygon {
+    pub points: Vec<Point<2>>,
+    pub color: Color32,
+    /// Distance of the face center from the viewer, along the view direction for parallel modes.
+    pub depth: f64,
+    /// Screen depth of each point.
+    pub depths: Vec<f64>,
+}
+
+/// Part of a face in front of the near plane of a perspective screen: Sutherland–Hodgman clipping
+/// against that single plane. Parallel screens keep the whole face.
+fn clip_near(screen: &Screen, points: &[&Point<3>]) -> Vec<Point<3>> {
+    if screen.mode.axes().is_some() {
+        return points.iter().map(|p| **p).collect();
+    }
+    let near = screen.camera.near;
+    let mut result: Vec<Point<3>> = vec![];
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        let (da, db) = (screen.depth(a), screen.depth(b));
+        if da >= near {
+            result.push(*a);
+        }
+        if (da >= near) != (db >= near) {
+            let t = (near - da) / (db - da);
+            result.push(Point { coords: add(a.coords, times(sub(b.coords, a.coords), t)) });
+        }
+    }
+    return result;
+}
+
+/// Shaded faces seen through the screen, farthest first so that nearer ones are painted over them.
+/// Faces reaching behind the near plane of a perspective screen are cut along it.
+pub fn shaded_faces(screen: &Screen, figure: &Figure<3>) -> Vec<Polygon> {
+    let camera = &screen.camera;
+    let mut polygons: Vec<Polygon> = vec![];
+    for face in figure.faces.iter() {
+        let points = clip_near(screen, &figure.face_points(face));
+        if points.len() < 3 {
+            continue;
+        }
+        let polygon = corners(figure, face);
+        // Ordered by the part that is seen.
+        let center = times(points.iter().fold([0.0; 3], |s, p| add(s, p.coords)), 1.0 / points.len() as f64);
+        let (toward, end) = toward_viewer(screen, center);
+        // Both sides of a face are lit, the one seen is the one facing the viewer.
+        let mut normal = face_normal(&polygon);
+        if dot(normal, toward) < 0.0 {
+            normal = times(normal, -1.0);
+        }
+        let depth = if end.is_finite() { dot(toward, toward).sqrt() } else { dot(sub(camera.target.coords, center), toward) };
+        polygons.push(Polygon {
+            points: points.iter().map(|p| screen.project(p)).collect(),
+            color: shade(screen.color, screen.shading.lambert(normal)),
+            depth,
+            depths: points.iter().map(|p| screen.depth(p)).collect(),
+        });
+    }
+    polygons.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    return polygons;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::points::loadFigure;
+    use crate::projection::{default_screens, ProjectionMode};
+
+    fn cube() -> Figure<3> {
+        return loadFigure(&std::fs::read_to_string("example/cube.fig").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn painter_order() {
+        let figure = cube();
+        let mut screens = default_screens(&Camera::looking_at(&figure));
+        screens[0].color = Color32::WHITE;
+        let faces = shaded_faces(&screens[0], &figure);
+        assert_eq!(6, faces.len());
+        assert!(faces.windows(2).all(|pair| pair[0].depth >= pair[1].depth));
+        // The front face is painted last, the back face first and both are lit alike.
+        let front = screens[0].project(&figure.points[0]);
+        assert!(faces[5].points.contains(&front));
+        assert!(!faces[0].points.contains(&front));
+        assert_eq!(faces[0].color, faces[5].color);
+
+        screens[0].mode = ProjectionMode::Isometric;
+        let faces = shaded_faces(&screens[0], &figure);
+        assert!(faces.windows(2).all(|pair| pair[0].depth >= pair[1].depth));
+        assert_eq!(6, faces.len());
+    }
+
+    #[test]
+    fn lambert() {
+        let shading = Shading { light: [0.0, 0.0, -3.0], ..Shading::default() };
+        assert_eq!(1.0, shading.lambert([0.0, 0.0, -1.0]));
+        assert_eq!(0.25, shading.lambert([0.0, 0.0, 1.0]));
+        assert_eq!(0.25, shading.lambert([1.0, 0.0, 0.0]));
+        assert_eq!(Color32::from_rgba_premultiplied(128, 0, 64, 0),
+                   shade(Color32::from_rgba_premultiplied(255, 0, 127, 0), 0.5));
+        for f in Fill::ALL {
+            assert_eq!(Some(f), Fill::from_name(f.name()));
+        }
+        assert!(Fill::Overlay.faces() && Fill::Overlay.edges());
+        assert!(!Fill::Wireframe.faces() && !Fill::Solid.edges());
+    }
+
+    #[test]
+    fn behind_the_eye() {
+        let figure = cube();
+        let mut camera = Camera::looking_at(&figure);
+        camera.eye.coords[2] = -50.0;
+        let screens = default_screens(&camera);
+        // The front face is entirely behind an eye inside the cube, the four sides are cut at the near plane.
+        let faces = shaded_faces(&screens[0], &figure);
+        assert_eq!(5, faces.len());
+        assert_eq!(1, faces.iter().filter(|f| f.depths.iter().all(|d| *d > camera.near)).count());
+        for f in faces.iter() {
+            assert_eq!(4, f.points.len());
+            assert!(f.depths.iter().all(|d| *d >= camera.near - 1e-9));
+            assert!(f.points.iter().all(|p| p.coords.iter().all(|c| c.is_finite())));
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use eframe::epaint::Color32;
+
+use crate::camera::Camera;
+use crate::projection::{ProjectionMode, Screen};
+
+/// Filter glasses: the first color is seen by the left eye, the second by the right eye.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anaglyph {
+    RedCyan,
+    GreenMagenta,
+    AmberBlue,
+}
+
+impl Anaglyph {
+    pub const ALL: [Anaglyph; 3] = [Anaglyph::RedCyan, Anaglyph::GreenMagenta, Anaglyph::AmberBlue];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Anaglyph::RedCyan => "red-cyan",
+            Anaglyph::GreenMagenta => "green-magenta",
+            Anaglyph::AmberBlue => "amber-blue",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Anaglyph> {
+        return Anaglyph::ALL.iter().find(|a| a.name() == name).copied();
+    }
+
+    pub fn filters(&self) -> [Color32; 2] {
+        match self {
+            Anaglyph::RedCyan => [Color32::from_rgb(255, 0, 0), Color32::from_rgb(0, 255, 255)],
+            Anaglyph::GreenMagenta => [Color32::from_rgb(0, 255, 0), Color32::from_rgb(255, 0, 255)],
+            Anaglyph::AmberBlue => [Color32::from_rgb(255, 191, 0), Color32::from_rgb(0, 0, 255)],
+        }
+    }
+
+    /// Keeps the part of `color` that goes through the filter of `eye` (0 left, 1 right).
+    /// The result is additive: overlapping lines of both eyes add up instead of hiding each other.
+    pub fn filter(&self, eye: usize, color: Color32) -> Color32 {
+        let f = self.filters()[eye];
+        let channel = |c: u8, f: u8| ((c as u16 * f as u16) / 255) as u8;
+        return Color32::from_rgba_premultiplied(
+            channel(color.r(), f.r()),
+            channel(color.g(), f.g()),
+            channel(color.b(), f.b()),
+            0);
+    }
+}
+
+/// Where the views of both eyes are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    /// Both views on the same area, separated by the anaglyph filters.
+    Overlay,
+    /// Left eye view on the left, for stereoscopes and parallel free viewing.
+    Parallel,
+    /// Left eye view on the right, for cross-eyed free viewing.
+    CrossEyed,
+}
+
+impl StereoLayout {
+    pub const ALL: [StereoLayout; 3] = [StereoLayout::Overlay, StereoLayout::Parallel, StereoLayout::CrossEyed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoLayout::Overlay => "overlay",
+            StereoLayout::Parallel => "parallel",
+            StereoLayout::CrossEyed => "cross-eyed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StereoLayout> {
+        return StereoLayout::ALL.iter().find(|l| l.name() == name).copied();
+    }
+}
+
+/// Left and right eye positions shared by the two screens of a stereo view.
+#[derive(Debug, Clone, Copy)]
+pub struct StereoRig {
+    /// Distance between the eyes, in figure units.
+    pub interocular: f64,
+    /// Depth beyond the camera target that appears on the screen plane, nearer points pop out.
+    pub convergence: f64,
+    pub anaglyph: Anaglyph,
+    pub layout: StereoLayout,
+}
+
+impl Default for StereoRig {
+    fn default() -> Self {
+        return StereoRig {
+            interocular: 60.0,
+            convergence: 0.0,
+            anaglyph: Anaglyph::RedCyan,
+            layout: StereoLayout::Overlay
+        };
+    }
+}
+
+impl StereoRig {
+    /// Sets the camera and viewport of each screen inside `area`, screens[0] being the left eye.
+    /// Both eyes are offset sideways from `camera`, parallel projections have no parallax.
+    pub fn apply(&self, screens: &mut [Screen; 2], camera: &Camera, area: [u32; 2]) {
+        let half = [area[0] / 2, area[1]];
+        let convergence = (camera.distance() + self.convergence).max(camera.near);
+        for (eye, s) in screens.iter_mut().enumerate() {
+            let offset = if eye == 0 { -self.interocular / 2.0 } else { self.interocular / 2.0 };
+            s.camera = match s.mode {
+                ProjectionMode::Perspective => camera.stereo_eye(offset, convergence),
+                _ => *camera
+            };
+            let (color, size, column) = match self.layout {
+                StereoLayout::Overlay => (self.anaglyph.filter(eye, Color32::WHITE), area, 0),
+                StereoLayout::Parallel => (Color32::WHITE, half, eye),
+                StereoLayout::CrossEyed => (Color32::WHITE, half, 1 - eye),
+            };
+            s.color = color;
+            s.size = size;
+            s.origin = [(column as u32 * half[0]) as f64, 0.0];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::points::Point;
+    use crate::projection::{default_screens, Projection, ToRealScreen, DEFAULT_AREA};
+
+    #[test]
+    fn filters() {
+        let gray = Color32::from_rgb(200, 100, 50);
+        assert_eq!(Color32::from_rgba_premultiplied(200, 0, 0, 0), Anaglyph::RedCyan.filter(0, gray));
+        assert_eq!(Color32::from_rgba_premultiplied(0, 100, 50, 0), Anaglyph::RedCyan.filter(1, gray));
+        assert_eq!(Color32::from_rgba_premultiplied(200, 0, 50, 0), Anaglyph::GreenMagenta.filter(1, gray));
+        assert_eq!(Color32::from_rgba_premultiplied(200, 74, 0, 0), Anaglyph::AmberBlue.filter(0, gray));
+        for a in Anaglyph::ALL {
+            assert_eq!(Some(a), Anaglyph::from_name(a.name()));
+            let [left, right] = a.filters();
+            // Complementary filters: each channel reaches at most one eye.
+            assert_eq!(0, left.r().min(right.r()));
+            assert_eq!(0, left.g().min(right.g()));
+            assert_eq!(0, left.b().min(right.b()));
+        }
+    }
+
+    #[test]
+    fn tint() {
+        let camera = Camera::default();
+        let mut screens = default_screens(&camera);
+        let orange = Color32::from_rgb(255, 136, 0);
+        for (eye, s) in screens.iter().enumerate() {
+            assert_eq!(StereoRig::default().anaglyph.filter(eye, orange), s.tint(orange));
+        }
+        StereoRig { layout: StereoLayout::Parallel, ..StereoRig::default() }.apply(&mut screens, &camera, DEFAULT_AREA);
+        assert_eq!(orange, screens[0].tint(orange));
+    }
+
+    #[test]
+    fn parallax() {
+        let camera = Camera::default();
+        let mut screens = default_screens(&camera);
+        let rig = StereoRig { convergence: 100.0, ..StereoRig::default() };
+        rig.apply(&mut screens, &camera, DEFAULT_AREA);
+        let project = |p: [f64; 3], eye: usize| -> f64 {
+            let point: Point<3> = Point { coords: p };
+            screens[eye].project(&point).coords[0]
+        };
+        let on_screen = [20.0, 10.0, 100.0];
+        assert!((project(on_screen, 0) - project(on_screen, 1)).abs() < 1e-9);
+        // Behind the convergence plane the left eye image is on the left, in front it is on the right.
+        let behind = [20.0, 10.0, 300.0];
+        assert!(project(behind, 0) < project(behind, 1));
+        let front = [20.0, 10.0, -100.0];
+        assert!(project(front, 0) > project(front, 1));
+        let y = |p: [f64; 3], eye: usize| {
+            let point: Point<3> = Point { coords: p };
+            screens[eye].project(&point).coords[1]
+        };
+        assert!((y(behind, 0) - y(behind, 1)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn layouts() {
+        let camera = Camera::default();
+        let mut screens = default_screens(&camera);
+        let point: Point<3> = Point { coords: [0.0, 0.0, 0.0] };
+        for layout in StereoLayout::ALL {
+            assert_eq!(Some(layout), StereoLayout::from_name(layout.name()));
+        }
+
+        let parallel = StereoRig { layout: StereoLayout::Parallel, ..StereoRig::default() };
+        parallel.apply(&mut screens, &camera, [800, 600]);
+        assert_eq!([400, 600], screens[0].size);
+        assert_eq!(Color32::WHITE, screens[0].color);
+        assert_eq!(200.0, screens[0].place(&screens[0].project(&point)).x);
+        assert_eq!(600.0, screens[1].place(&screens[1].project(&point)).x);
+
+        let crossed = StereoRig { layout: StereoLayout::CrossEyed, ..StereoRig::default() };
+        crossed.apply(&mut screens, &camera, [800, 600]);
+        assert_eq!(600.0, screens[0].place(&screens[0].project(&point)).x);
+        assert_eq!(200.0, screens[1].place(&screens[1].project(&point)).x);
+        assert!(screens[0].viewport().contains(screens[0].place(&screens[0].project(&point))));
+
+        StereoRig::default().apply(&mut screens, &camera, [800, 600]);
+        assert_eq!([800, 600], screens[1].size);
+        assert_eq!([0.0, 0.0], screens[1].origin);
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use eframe::epaint::Color32;
+
+use crate::hidden::{edge_pieces, figure_runs, Run};
+use crate::points::{Dash, Edge, Figure, Marker};
+use crate::projection::{drawing_size, Screen, ToRealScreen, BACKGROUND, CROSS_WIDTH, DASH, DOT_SPACING, EDGE_WIDTH, LABEL_SIZE};
+use crate::shading::shaded_faces;
+
+fn color(c: Color32) -> String {
+    return format!("#{:02x}{:02x}{:02x}", c.r(), c.g(), c.b());
+}
+
+/// Opacity attribute of partly transparent colors.
+fn opacity(attribute: &str, c: Color32) -> String {
+    if c.a() != 255 && c.a() != 0 {
+        return format!(" {}=\"{:.3}\"", attribute, c.a() as f32 / 255.0);
+    }
+    return String::new();
+}
+
+fn polygons(s: &Screen, f: &Figure<3>) -> String {
+    let mut result = String::new();
+    for polygon in shaded_faces(s, f) {
+        let points: Vec<String> = polygon.points.iter()
+            .map(|p| s.place(p))
+            .map(|p| format!("{:.2},{:.2}", p.x, p.y))
+            .collect();
+        result.push_str(&format!("  <polygon points=\"{}\" fill=\"{}\"{}/>\n",
+                                 points.join(" "), color(polygon.color), opacity("fill-opacity", polygon.color)));
+    }
+    return result;
+}
+
+fn line(s: &Screen, e: &Edge, f: &Figure<3>, runs: &[Run]) -> String {
+    let mut result = String::new();
+    let stroke = s.tint(e.style.color32());
+    for piece in edge_pieces(s, f, e, runs) {
+        let (p1, p2) = (s.place(&piece.ends[0]), s.place(&piece.ends[1]));
+        result.push_str(&format!("  <line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" stroke-width=\"{}\"",
+                                 p1.x, p1.y, p2.x, p2.y, color(stroke), e.style.width.unwrap_or(EDGE_WIDTH)));
+        result.push_str(&opacity("stroke-opacity", stroke));
+        match piece.dash(e) {
+            Dash::Solid => {},
+            Dash::Dashed => result.push_str(&format!(" stroke-dasharray=\"{} {}\"", DASH[0], DASH[1])),
+            // Zero length dashes with round caps are dots.
+            Dash::Dotted => result.push_str(&format!(" stroke-dasharray=\"0 {}\" stroke-linecap=\"round\"", DOT_SPACING)),
+        }
+        result.push_str("/>\n");
+    }
+    return result;
+}
+
+fn escape(text: &str) -> String {
+    return text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
+
+/// Markers and labels of the points that have some.
+fn points(s: &Screen, f: &Figure<3>) -> String {
+    let mut result = String::new();
+    let fill = s.tint(Color32::WHITE);
+    let paint = format!("fill=\"{}\"{}", color(fill), opacity("fill-opacity", fill));
+    for (index, style) in f.point_styles.iter() {
+        let at = match s.visible_point(&f.points[*index]) {
+            Some(p) => s.place(&p),
+            None => continue
+        };
+        let half = style.size / 2.0;
+        match style.marker {
+            Some(Marker::Circle) => result.push_str(&format!("  <circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{}\" {}/>\n",
+                                                             at.x, at.y, half, paint)),
+            Some(Marker::Square) => result.push_str(&format!("  <rect x=\"{:.2}\" y=\"{:.2}\" width=\"{}\" height=\"{}\" {}/>\n",
+                                                             at.x - half, at.y - half, style.size, style.size, paint)),
+            Some(Marker::Cross) => {
+                for (dx, dy) in [(half, half), (half, -half)] {
+                    result.push_str(&format!("  <line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" stroke-width=\"{}\"{}/>\n",
+                                             at.x - dx, at.y - dy, at.x + dx, at.y + dy, color(fill), CROSS_WIDTH,
+                                             opacity("stroke-opacity", fill)));
+                }
+            },
+            None => {}
+        }
+        if let Some(label) = &style.label {
+            result.push_str(&format!("  <text x=\"{:.2}\" y=\"{:.2}\" font-family=\"sans-serif\" font-size=\"{}\" {}>{}</text>\n",
+                                     at.x + half + 2.0, at.y - half - 2.0, LABEL_SIZE, paint, escape(label)));
+        }
+    }
+    return result;
+}
+
+/// Draws the faces, edges and point markers of the figure through each screen, in the order and colors used by the GUI.
+/// Each screen is clipped to its viewport.
+pub fn render_svg(figure: &Figure<3>, screens: &[Screen]) -> String {
+    let [width, height] = drawing_size(screens);
+    let mut result = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+                             width, height, width, height);
+    result.push_str(&format!("  <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n", color(BACKGROUND)));
+    result.push_str("  <defs>\n");
+    for (i, s) in screens.iter().enumerate() {
+        let v = s.viewport();
+        result.push_str(&format!("    <clipPath id=\"viewport{}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/></clipPath>\n",
+                                 i, v.min.x, v.min.y, v.width(), v.height()));
+    }
+    result.push_str("  </defs>\n");
+    for (i, s) in screens.iter().enumerate() {
+        result.push_str(&format!("  <g clip-path=\"url(#viewport{})\"", i));
+        // Transparent premultiplied colors are additive, as in egui.
+        if s.color.a() == 0 {
+            result.push_str(" style=\"mix-blend-mode:screen\"");
+        }
+        result.push_str(">\n");
+        if s.shading.fill.faces() {
+            result.push_str(&polygons(s, figure));
+        }
+        if s.shading.fill.edges() {
+            let runs = figure_runs(s, figure);
+            figure.edges.iter().zip(runs.iter()).for_each(|(e, r)| result.push_str(&line(s, e, figure, r)));
+        }
+        result.push_str(&points(s, figure));
+        result.push_str("  </g>\n");
+    }
+    result.push_str("</svg>\n");
+    return result;
+}
+
+pub fn write_svg(path: &Path, figure: &Figure<3>, screens: &[Screen]) -> io::Result<()> {
+    return fs::write(path, render_svg(figure, screens));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hidden::HiddenLines;
+    use crate::projection::{default_screens, Clipping, ProjectionMode};
+    use crate::shading::{Fill, Shading};
+    use crate::points::{loadFigure, EdgeAdder, FaceAdder, PointAdder};
+
+    #[test]
+    fn triangle() {
+        let mut f: Figure<3> = Figure::new();
+        f.add_point([0.0, 0.0, 0.0]);
+        f.add_point([100.0, 0.0, 0.0]);
+        f.add_point([0.0, 100.0, 50.0]);
+        f.add_edge(0, 1);
+        f.add_edge(1, 2);
+        f.add_edge(2, 0);
+        let screen = Screen {
+            camera: Camera::default(),
+            mode: ProjectionMode::Perspective,
+            clipping: Clipping::Near,
+            hidden_lines: HiddenLines::Show,
+            shading: Shading::default(),
+            color: Color32::RED,
+            size: [900, 700],
+            origin: [0.0, 0.0],
+        };
+        let svg = render_svg(&f, std::slice::from_ref(&screen));
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"900\" height=\"700\""));
+        assert_eq!(3, svg.matches("<line ").count());
+        assert!(svg.contains("<line x1=\"450.00\" y1=\"350.00\" x2=\"571.24\" y2=\"350.00\" stroke=\"#ff0000\" stroke-width=\"2.4\"/>"),
+                "{}", svg);
+        assert!(svg.contains("x2=\"450.00\" y2=\"239.78\""), "{}", svg);
+        assert!(svg.contains("<clipPath id=\"viewport0\"><rect x=\"0\" y=\"0\" width=\"900\" height=\"700\"/></clipPath>"));
+        assert_eq!(0, svg.matches("<polygon ").count());
+
+        f.add_face(&[0, 1, 2]);
+        let solid = Screen { shading: Shading { fill: Fill::Solid, ..Shading::default() }, ..screen };
+        let svg = render_svg(&f, &[solid]);
+        assert_eq!(0, svg.matches("<line ").count());
+        assert!(svg.contains("<polygon points=\"450.00,350.00 571.24,350.00 450.00,239.78\" fill=\"#"), "{}", svg);
+    }
+
+    #[test]
+    fn styles() {
+        let f: Figure<3> = loadFigure(&fs::read_to_string("example/pyramid.fig").unwrap()).unwrap();
+        let screens = default_screens(&Camera::looking_at(&f));
+        let svg = render_svg(&f, &screens[..1]);
+        assert_eq!(2, svg.matches("stroke=\"#ff0000\" stroke-width=\"4\"").count(), "{}", svg);
+        assert_eq!(1, svg.matches("stroke-dasharray=\"0 6\" stroke-linecap=\"round\"").count());
+        assert_eq!(1, svg.matches("stroke-dasharray=\"6 4\"").count());
+        // Through the red filter of the left eye, only the red part of the blue edge is left.
+        assert!(svg.contains("stroke=\"#400000\" stroke-width=\"2.4\""));
+        assert_eq!(2, svg.matches("<circle ").count());
+        assert_eq!(1, svg.matches("width=\"6\" height=\"6\"").count());
+        assert_eq!(4, svg.matches("<text ").count());
+        assert!(svg.contains(">apex</text>"));
+        assert_eq!("a &lt;b&gt; &amp; &quot;c&quot;", escape("a <b> & \"c\""));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::matrix::{Matrix, Vector};
+use crate::points::{Figure, Point, Scalar};
+
+impl<const N: usize, T: Scalar> Point<N, T> {
+    pub fn transform(&self, m: &Matrix<f64, f64, N>) -> Point<N> {
+        let v: Vector<f64, N> = Vector { values: self.coords.map(|c| c.to_f64()) };
+        return Point { coords: m.apply(&v).values };
+    }
+}
+
+impl<T: Scalar> Point<3, T> {
+    /// Applies a homogeneous transform, dividing by `w` when it is not 1.
+    pub fn transform_affine(&self, m: &Matrix<f64, f64, 4>) -> Point<3> {
+        let v: Vector<f64, 4> = Vector {
+            values: [self.coords[0].to_f64(), self.coords[1].to_f64(), self.coords[2].to_f64(), 1.0]
+        };
+        let r = m.apply(&v).values;
+        let w = if r[3] == 0.0 { 1.0 } else { r[3] };
+        return Point { coords: [r[0] / w, r[1] / w, r[2] / w] };
+    }
+}
+
+impl<const N: usize, T: Scalar> Figure<N, T> {
+    /// Linear transform of every point, edges are kept.
+    pub fn transform(&self, m: &Matrix<f64, f64, N>) -> Figure<N> {
+        return Figure {
+            points: self.points.iter().map(|p| p.transform(m)).collect(),
+            edges: self.edges.clone(),
+            faces: self.faces.clone(),
+            point_styles: self.point_styles.clone()
+        };
+    }
+}
+
+impl<T: Scalar> Figure<3, T> {
+    pub fn transform_affine(&self, m: &Matrix<f64, f64, 4>) -> Figure<3> {
+        return Figure {
+            points: self.points.iter().map(|p| p.transform_affine(m)).collect(),
+            edges: self.edges.clone(),
+            faces: self.faces.clone(),
+            point_styles: self.point_styles.clone()
+        };
+    }
+
+    /// Rotates around the centroid: `yaw` turns around the vertical axis, then `pitch` around
+    /// the horizontal one. Angles are in radians.
+    pub fn rotated(&self, yaw: f64, pitch: f64) -> Figure<3> {
+        let c = self.centroid().coords;
+        let rotation = Matrix::rotation_x(pitch) * Matrix::rotation_y(yaw);
+        let m = Matrix::translation(c)
+            * Matrix::affine(&rotation, [0.0; 3])
+            * Matrix::translation([-c[0], -c[1], -c[2]]);
+        return self.transform_affine(&m);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::points::{EdgeAdder, PointAdder};
+    use std::f64::consts::FRAC_PI_2;
+
+    fn assert_near<const N: usize>(expected: [f64; N], p: &Point<N>) {
+        for i in 0..N {
+            assert!((expected[i] - p.coords[i]).abs() < 1e-9, "{:?} != {:?}", expected, p.coords);
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        let p: Point<3, i64> = Point { coords: [1, 2, 3] };
+        assert_near([1.0, -3.0, 2.0], &p.transform(&Matrix::rotation_x(FRAC_PI_2)));
+        assert_near([3.0, 2.0, -1.0], &p.transform(&Matrix::rotation_y(FRAC_PI_2)));
+        assert_near([-2.0, 1.0, 3.0], &p.transform(&Matrix::rotation_z(FRAC_PI_2)));
+
+        let xy_then_zw: Matrix<f64, f64, 4> = Matrix::rotation(2, 3, FRAC_PI_2) * Matrix::rotation(0, 1, FRAC_PI_2);
+        let q: Point<4> = Point { coords: [1.0, 2.0, 3.0, 4.0] };
+        assert_near([-2.0, 1.0, -4.0, 3.0], &q.transform(&xy_then_zw));
+    }
+
+    #[test]
+    fn affine() {
+        let p: Point<3> = Point { coords: [1.0, 2.0, 3.0] };
+        assert_near([11.0, 22.0, 33.0], &p.transform_affine(&Matrix::translation([10.0, 20.0, 30.0])));
+
+        let scale_then_move = Matrix::translation([1.0, 0.0, 0.0])
+            * Matrix::affine(&Matrix::scaling([2.0, 3.0, 4.0]), [0.0; 3]);
+        assert_near([3.0, 6.0, 12.0], &p.transform_affine(&scale_then_move));
+        let move_then_scale = Matrix::affine(&Matrix::scaling([2.0, 3.0, 4.0]), [0.0; 3])
+            * Matrix::translation([1.0, 0.0, 0.0]);
+        assert_near([4.0, 6.0, 12.0], &p.transform_affine(&move_then_scale));
+    }
+
+    #[test]
+    fn figure() {
+        let mut f: Figure<3, i64> = Figure::new();
+        f.add_point([1, 0, 0]);
+        f.add_point([0, 1, 0]);
+        f.add_edge(0, 1);
+        let scaled = f.transform(&Matrix::scaling([2.0, 2.0, 2.0]));
+        assert_near([2.0, 0.0, 0.0], &scaled.points[0]);
+        assert_near([0.0, 2.0, 0.0], &scaled.points[1]);
+        assert_eq!(f.edges, scaled.edges);
+    }
+}